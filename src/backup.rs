@@ -0,0 +1,183 @@
+use std::{
+  fs,
+  path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use time::{
+  format_description::BorrowedFormatItem, macros::format_description, OffsetDateTime,
+  PrimitiveDateTime,
+};
+use tracing::info;
+
+/// Format of the names of backups, the time they were taken at
+const NAME_FORMAT: &[BorrowedFormatItem] =
+  format_description!("[year][month][day]-[hour][minute][second]");
+
+/// Copy the existing `dirs` of `hugo_dir` into a new timestamped dir of `backup_dir`,
+/// then delete the oldest backups beyond `keep`
+pub fn backup(hugo_dir: &Path, dirs: &[&PathBuf], backup_dir: &Path, keep: usize) -> Result<()> {
+  let dirs: Vec<&PathBuf> = dirs.iter().copied().filter(|dir| dir.is_dir()).collect();
+  // A fresh site has nothing to back up
+  if dirs.is_empty() {
+    return Ok(());
+  }
+  fs::create_dir_all(backup_dir)
+    .with_context(|| format!("Cannot create backup dir {backup_dir:?}"))?;
+  // Other dirs may share the backup dir, only those named like backups are rotated
+  let mut backups = vec![];
+  for res in fs::read_dir(backup_dir).with_context(|| format!("Cannot read {backup_dir:?}"))? {
+    let path = res?.path();
+    let key = path
+      .file_name()
+      .and_then(|name| name.to_str())
+      .and_then(backup_key);
+    if let Some(key) = key.filter(|_| path.is_dir()) {
+      backups.push((key, path));
+    }
+  }
+  backups.sort();
+
+  let name = OffsetDateTime::now_utc().format(NAME_FORMAT).unwrap();
+  // Backups taken in the same second get a suffix numbering them
+  let (time, _) = backup_key(&name).unwrap();
+  let last = backups.iter().rfind(|((t, _), _)| *t == time);
+  let dst = (last.map_or(1, |((_, n), _)| n + 1)..)
+    .map(|n| match n {
+      1 => backup_dir.join(&name),
+      n => backup_dir.join(format!("{name}-{n}")),
+    })
+    .find(|path| !path.exists())
+    .unwrap();
+  info!(dir = %dst.display(), "backup");
+  for dir in dirs {
+    let sub_path = dir.strip_prefix(hugo_dir).unwrap_or(dir);
+    copy_dir(dir, &dst.join(sub_path))
+      .with_context(|| format!("Cannot back {dir:?} up into {dst:?}"))?;
+  }
+
+  // The new backup is always kept
+  let stale = (backups.len() + 1).saturating_sub(keep.max(1));
+  for (_, path) in &backups[..stale] {
+    info!(dir = %path.display(), "delete backup");
+    fs::remove_dir_all(path).with_context(|| format!("Cannot delete backup {path:?}"))?;
+  }
+  Ok(())
+}
+
+/// The time and the number of the backup named `name` among those taken in the same second,
+/// `None` if `name` is no backup name
+fn backup_key(name: &str) -> Option<(PrimitiveDateTime, u32)> {
+  let mut parts = name.splitn(3, '-');
+  let time = format!("{}-{}", parts.next()?, parts.next()?);
+  let time = PrimitiveDateTime::parse(&time, NAME_FORMAT).ok()?;
+  let n = match parts.next() {
+    Some(n) => n.parse().ok().filter(|n| *n > 1)?,
+    None => 1,
+  };
+  Some((time, n))
+}
+
+fn copy_dir(src: &Path, dst: &Path) -> Result<()> {
+  fs::create_dir_all(dst)?;
+  for res in fs::read_dir(src)? {
+    let entry = res?;
+    let path = entry.path();
+    if path.is_dir() {
+      copy_dir(&path, &dst.join(entry.file_name()))?;
+    } else {
+      fs::copy(&path, dst.join(entry.file_name()))?;
+    }
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{env, process};
+
+  use super::*;
+
+  /// An empty temp dir for the test `name`
+  fn temp_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("tecexp-{}-{name}", process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+  }
+
+  fn dir_names(dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(dir)
+      .unwrap()
+      .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+      .collect();
+    names.sort();
+    names
+  }
+
+  #[test]
+  fn backup_key_parses_backup_names_only() {
+    assert!(backup_key("20240102-030405").is_some());
+    assert_eq!(backup_key("20240102-030405-2").map(|(_, n)| n), Some(2));
+    assert!(backup_key("20240102-030405-1").is_none());
+    assert!(backup_key("20240102-030405-x").is_none());
+    assert!(backup_key("css").is_none());
+    assert!(backup_key("zz").is_none());
+    assert!(backup_key("2024-01").is_none());
+  }
+
+  #[test]
+  fn rotation_keeps_foreign_dirs_and_the_new_backup() {
+    let hugo_dir = temp_dir("rotation");
+    let posts = hugo_dir.join("content/posts");
+    fs::create_dir_all(&posts).unwrap();
+    fs::write(posts.join("a.md"), "a").unwrap();
+    let backup_dir = hugo_dir.join("shared");
+    for dir in ["css", "js", "zz", "20000101-000000", "20000101-000000-2"] {
+      fs::create_dir_all(backup_dir.join(dir)).unwrap();
+    }
+
+    backup(&hugo_dir, &[&posts], &backup_dir, 1).unwrap();
+    let names = dir_names(&backup_dir);
+    assert_eq!(names.len(), 4, "{names:?}");
+    assert_eq!(&names[1..], ["css", "js", "zz"]);
+    assert!(backup_key(&names[0]).is_some_and(|(time, _)| time.year() > 2000));
+    assert!(backup_dir
+      .join(&names[0])
+      .join("content/posts/a.md")
+      .is_file());
+
+    // A second backup in the same second gets a suffix rather than merging into the first
+    backup(&hugo_dir, &[&posts], &backup_dir, 2).unwrap();
+    let backups: Vec<String> = dir_names(&backup_dir)
+      .into_iter()
+      .filter(|name| backup_key(name).is_some())
+      .collect();
+    assert_eq!(backups.len(), 2, "{backups:?}");
+    // The oldest backup is deleted, though a later one of the same second may sort before it
+    backup(&hugo_dir, &[&posts], &backup_dir, 2).unwrap();
+    let kept: Vec<String> = dir_names(&backup_dir)
+      .into_iter()
+      .filter(|name| backup_key(name).is_some())
+      .collect();
+    assert_eq!(kept.len(), 2, "{kept:?}");
+    assert!(kept.contains(&backups[1]), "{kept:?}");
+    fs::remove_dir_all(&hugo_dir).unwrap();
+  }
+
+  #[test]
+  fn missing_dirs_are_handled() {
+    let hugo_dir = temp_dir("missing");
+    let posts = hugo_dir.join("content/posts");
+    let backup_dir = hugo_dir.join("backups");
+
+    // Nothing to back up on a fresh site
+    backup(&hugo_dir, &[&posts], &backup_dir, 5).unwrap();
+    assert!(!backup_dir.exists());
+
+    fs::create_dir_all(&posts).unwrap();
+    backup(&hugo_dir, &[&posts], &backup_dir, 5).unwrap();
+    assert_eq!(dir_names(&backup_dir).len(), 1);
+    fs::remove_dir_all(&hugo_dir).unwrap();
+  }
+}
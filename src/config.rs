@@ -0,0 +1,826 @@
+use std::{
+  collections::BTreeMap,
+  fs,
+  path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use serde_yaml::Value;
+
+use crate::{
+  props::FrontMatterFormat,
+  publish::PublishBy,
+  slug::{SlugCollision, SlugTransliterate, SlugUnicode},
+  GlobalArgs,
+};
+
+/// Config file looked up in the working dir when `--config` is not given
+pub const DEFAULT_CONFIG_FILE: &str = "tecexp.toml";
+
+/// Urls of videos embedded with the shortcodes of Hugo, `{id}` standing for the video
+const DEFAULT_VIDEOS: [(&str, &str); 5] = [
+  ("youtube.com/watch?v={id}", "{{< youtube {id} >}}"),
+  ("youtube.com/shorts/{id}", "{{< youtube {id} >}}"),
+  ("youtu.be/{id}", "{{< youtube {id} >}}"),
+  ("vimeo.com/{id}", "{{< vimeo {id} >}}"),
+  ("player.vimeo.com/video/{id}", "{{< vimeo {id} >}}"),
+];
+
+/// Settings as written in the TOML config file, every key is optional
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigFile {
+  obsidian_dir: Option<PathBuf>,
+  vaults: Vec<VaultConfig>,
+  hugo_dir: Option<PathBuf>,
+  hugo_posts_dir: Option<PathBuf>,
+  hugo_assets_dir: Option<PathBuf>,
+  base_path: Option<String>,
+  posts_url: Option<String>,
+  assets_url: Option<String>,
+  publish_key: Option<String>,
+  publish: Option<OneOrMany>,
+  publish_draft: Option<OneOrMany>,
+  publish_strict: Option<bool>,
+  publish_tag: Vec<String>,
+  publish_by: Option<PublishBy>,
+  include: Vec<String>,
+  exclude: Vec<String>,
+  sections: BTreeMap<PathBuf, PathBuf>,
+  rename_props: BTreeMap<String, String>,
+  exclude_props: Vec<String>,
+  front_matter_format: Option<FrontMatterFormat>,
+  front_matter_template: Option<PathBuf>,
+  date_from: Vec<String>,
+  lastmod_from: Vec<String>,
+  date_from_git: Option<bool>,
+  filename_date_format: Option<String>,
+  date_format: Option<String>,
+  timezone: Option<String>,
+  description_length: Option<usize>,
+  cover_map: Option<bool>,
+  math_prop: Option<bool>,
+  inline_tags_prop: Option<bool>,
+  nested_tags: Option<NestedTags>,
+  author: Option<OneOrMany>,
+  taxonomies: BTreeMap<String, TaxonomyConfig>,
+  cascade: BTreeMap<PathBuf, BTreeMap<String, Value>>,
+  embed_depth: Option<usize>,
+  shift_headings: Option<usize>,
+  start_marker: Option<String>,
+  end_marker: Option<String>,
+  more_marker: Option<String>,
+  private_placeholder: Option<String>,
+  figure_shortcode: Option<FigureShortcode>,
+  figure_caption: Option<bool>,
+  callouts: Option<Callouts>,
+  callout_shortcode: Option<String>,
+  unpublished_link: Option<String>,
+  highlight: Option<String>,
+  mermaid: Option<String>,
+  videos: Option<BTreeMap<String, String>>,
+  tasks: Option<Tasks>,
+  raw_html: Option<RawHtml>,
+  inline_tags: Option<InlineTags>,
+  dataview: Option<Dataview>,
+  dataview_placeholder: Option<String>,
+  math_delimiters: Option<MathDelimiters>,
+  slug_unicode: Option<SlugUnicode>,
+  slug_transliterate: Option<SlugTransliterate>,
+  slug_collision: Option<SlugCollision>,
+  rename_aliases: Option<bool>,
+  mirror_folders: Option<bool>,
+  backlinks: Option<Backlinks>,
+  backlinks_title: Option<String>,
+  graph: Option<bool>,
+  trash: Option<bool>,
+  backup_dir: Option<PathBuf>,
+  backup_keep: Option<usize>,
+  /// Named export targets, each overriding the top level settings
+  profiles: BTreeMap<String, ConfigFile>,
+}
+
+impl ConfigFile {
+  /// Settings of the profile, falling back to the top level ones of `base`
+  fn overlay(self, base: &ConfigFile) -> ConfigFile {
+    let has_vaults = self.obsidian_dir.is_some() || !self.vaults.is_empty();
+    ConfigFile {
+      obsidian_dir: if has_vaults {
+        self.obsidian_dir
+      } else {
+        base.obsidian_dir.clone()
+      },
+      vaults: if has_vaults {
+        self.vaults
+      } else {
+        base.vaults.clone()
+      },
+      hugo_dir: self.hugo_dir.or_else(|| base.hugo_dir.clone()),
+      hugo_posts_dir: self.hugo_posts_dir.or_else(|| base.hugo_posts_dir.clone()),
+      hugo_assets_dir: self
+        .hugo_assets_dir
+        .or_else(|| base.hugo_assets_dir.clone()),
+      base_path: self.base_path.or_else(|| base.base_path.clone()),
+      posts_url: self.posts_url.or_else(|| base.posts_url.clone()),
+      assets_url: self.assets_url.or_else(|| base.assets_url.clone()),
+      publish_key: self.publish_key.or_else(|| base.publish_key.clone()),
+      publish: self.publish.or_else(|| base.publish.clone()),
+      publish_draft: self.publish_draft.or_else(|| base.publish_draft.clone()),
+      publish_strict: self.publish_strict.or(base.publish_strict),
+      publish_tag: or_base(self.publish_tag, &base.publish_tag),
+      publish_by: self.publish_by.or(base.publish_by),
+      include: or_base(self.include, &base.include),
+      exclude: or_base(self.exclude, &base.exclude),
+      sections: if self.sections.is_empty() {
+        base.sections.clone()
+      } else {
+        self.sections
+      },
+      rename_props: if self.rename_props.is_empty() {
+        base.rename_props.clone()
+      } else {
+        self.rename_props
+      },
+      exclude_props: or_base(self.exclude_props, &base.exclude_props),
+      front_matter_format: self.front_matter_format.or(base.front_matter_format),
+      front_matter_template: self
+        .front_matter_template
+        .or_else(|| base.front_matter_template.clone()),
+      date_from: or_base(self.date_from, &base.date_from),
+      lastmod_from: or_base(self.lastmod_from, &base.lastmod_from),
+      date_from_git: self.date_from_git.or(base.date_from_git),
+      filename_date_format: self
+        .filename_date_format
+        .or_else(|| base.filename_date_format.clone()),
+      date_format: self.date_format.or_else(|| base.date_format.clone()),
+      timezone: self.timezone.or_else(|| base.timezone.clone()),
+      description_length: self.description_length.or(base.description_length),
+      cover_map: self.cover_map.or(base.cover_map),
+      math_prop: self.math_prop.or(base.math_prop),
+      inline_tags_prop: self.inline_tags_prop.or(base.inline_tags_prop),
+      nested_tags: self.nested_tags.or(base.nested_tags),
+      author: self.author.or_else(|| base.author.clone()),
+      taxonomies: if self.taxonomies.is_empty() {
+        base.taxonomies.clone()
+      } else {
+        self.taxonomies
+      },
+      cascade: if self.cascade.is_empty() {
+        base.cascade.clone()
+      } else {
+        self.cascade
+      },
+      embed_depth: self.embed_depth.or(base.embed_depth),
+      shift_headings: self.shift_headings.or(base.shift_headings),
+      start_marker: self.start_marker.or_else(|| base.start_marker.clone()),
+      end_marker: self.end_marker.or_else(|| base.end_marker.clone()),
+      more_marker: self.more_marker.or_else(|| base.more_marker.clone()),
+      private_placeholder: self
+        .private_placeholder
+        .or_else(|| base.private_placeholder.clone()),
+      figure_shortcode: self.figure_shortcode.or(base.figure_shortcode),
+      figure_caption: self.figure_caption.or(base.figure_caption),
+      callouts: self.callouts.or(base.callouts),
+      callout_shortcode: self
+        .callout_shortcode
+        .or_else(|| base.callout_shortcode.clone()),
+      unpublished_link: self
+        .unpublished_link
+        .or_else(|| base.unpublished_link.clone()),
+      highlight: self.highlight.or_else(|| base.highlight.clone()),
+      mermaid: self.mermaid.or_else(|| base.mermaid.clone()),
+      videos: self.videos.or_else(|| base.videos.clone()),
+      tasks: self.tasks.or(base.tasks),
+      raw_html: self.raw_html.or(base.raw_html),
+      inline_tags: self.inline_tags.or(base.inline_tags),
+      dataview: self.dataview.or(base.dataview),
+      dataview_placeholder: self
+        .dataview_placeholder
+        .or_else(|| base.dataview_placeholder.clone()),
+      math_delimiters: self.math_delimiters.or(base.math_delimiters),
+      slug_unicode: self.slug_unicode.or(base.slug_unicode),
+      slug_transliterate: self.slug_transliterate.or(base.slug_transliterate),
+      slug_collision: self.slug_collision.or(base.slug_collision),
+      rename_aliases: self.rename_aliases.or(base.rename_aliases),
+      mirror_folders: self.mirror_folders.or(base.mirror_folders),
+      backlinks: self.backlinks.or(base.backlinks),
+      backlinks_title: self
+        .backlinks_title
+        .or_else(|| base.backlinks_title.clone()),
+      graph: self.graph.or(base.graph),
+      trash: self.trash.or(base.trash),
+      backup_dir: self.backup_dir.or_else(|| base.backup_dir.clone()),
+      backup_keep: self.backup_keep.or(base.backup_keep),
+      profiles: BTreeMap::new(),
+    }
+  }
+}
+
+/// A single value or a list of values
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum OneOrMany {
+  One(String),
+  Many(Vec<String>),
+}
+
+impl From<OneOrMany> for Vec<String> {
+  fn from(value: OneOrMany) -> Self {
+    match value {
+      OneOrMany::One(value) => vec![value],
+      OneOrMany::Many(values) => values,
+    }
+  }
+}
+
+/// An Obsidian vault exported into the Hugo site
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VaultConfig {
+  /// Obsidian vault dir
+  pub dir: PathBuf,
+  /// Hugo posts sub dir of the vault, `hugo_posts_dir` if not set
+  pub hugo_posts_dir: Option<PathBuf>,
+}
+
+impl VaultConfig {
+  fn new(dir: PathBuf) -> Self {
+    Self {
+      dir,
+      hugo_posts_dir: None,
+    }
+  }
+}
+
+/// A Hugo taxonomy filled from a prop of the notes
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TaxonomyConfig {
+  /// Prop holding the terms, the name of the taxonomy if not set
+  pub prop: Option<String>,
+  /// How the terms are written
+  pub terms: TermStyle,
+}
+
+/// How the terms of a taxonomy are written
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TermStyle {
+  /// As in the note
+  #[default]
+  Keep,
+  /// Lower case
+  Lower,
+  /// Lower case with dashes, like urls
+  Slug,
+}
+
+/// Which embedded images are written as Hugo `figure` shortcodes, md images otherwise
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FigureShortcode {
+  Never,
+  /// Images with a size like `![[img.png|400]]`, which md images cannot have
+  #[default]
+  Sized,
+  Always,
+}
+
+/// How math is delimited in posts
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MathDelimiters {
+  /// As in Obsidian, `$inline$` and `$$display$$`
+  #[default]
+  Dollars,
+  /// `\(inline\)` and `\[display\]`, as the passthrough extension of Hugo is set up in its docs
+  Brackets,
+}
+
+/// What tasks like `- [ ] Todo` and `- [x] Done` become
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Tasks {
+  /// As they are, Hugo rendering them as read-only checkboxes
+  #[default]
+  Keep,
+  /// Read-only HTML checkboxes, for all statuses like `[/]` and when Hugo's task lists are off
+  Checkbox,
+  /// Left out unless done, with the lines nested in them
+  Strip,
+}
+
+/// What the HTML written in notes becomes, which Hugo leaves out unless `markup.goldmark.renderer.unsafe` is on
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RawHtml {
+  /// As it is
+  #[default]
+  Keep,
+  /// Tags and comments left out, their text kept unless in elements like `<script>`
+  Strip,
+  /// Elements running code like `<script>` and `<iframe>` left out, with event handlers and `javascript:` urls
+  Sanitize,
+}
+
+/// What nested tags like `area/topic` become
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NestedTags {
+  /// As they are, Hugo writing their urls like `/tags/area/topic/`
+  #[default]
+  Keep,
+  /// Their last part, like `topic`
+  Leaf,
+  /// A tag for every part, like `area` and `topic`
+  Split,
+}
+
+impl NestedTags {
+  /// The tags nested tag `tag` becomes
+  pub fn apply(self, tag: &str) -> Vec<String> {
+    let mut parts = tag.split('/').filter(|part| !part.is_empty());
+    match self {
+      Self::Keep => vec![tag.to_string()],
+      Self::Leaf => parts.next_back().map(str::to_string).into_iter().collect(),
+      Self::Split => parts.map(str::to_string).collect(),
+    }
+  }
+}
+
+/// What the `#tags` written in notes become
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InlineTags {
+  /// Text as they are
+  #[default]
+  Keep,
+  /// Left out, the tags of the post listing them
+  Strip,
+  /// Links to the pages of the tags, like `[#book](/tags/book/)`
+  Link,
+}
+
+/// What the queries of the Dataview plugin become, which only it runs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Dataview {
+  /// Code blocks and code spans as they are
+  #[default]
+  Keep,
+  /// Left out
+  Strip,
+  /// Query blocks replaced with a placeholder, inline queries left out
+  Placeholder,
+}
+
+/// What Obsidian callouts like `> [!note] Title` become
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Callouts {
+  /// Blockquotes starting with their title in bold
+  #[default]
+  Blockquote,
+  /// Shortcodes of the theme like `{{< admonition note "Title" >}}`
+  Shortcode,
+  /// Blockquotes as they are, for the blockquote render hooks of Hugo
+  Keep,
+}
+
+/// Where posts list the posts linking to them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backlinks {
+  #[default]
+  None,
+  /// A section at the end of the content
+  Section,
+  /// A `backlinks` prop of titles and urls, for themes to render
+  Props,
+}
+
+/// Resolved settings, merged from the config file and CLI flags
+#[derive(Debug, Clone)]
+pub struct Config {
+  /// Name of the profile, `None` for the top level settings
+  pub name: Option<String>,
+  pub vaults: Vec<VaultConfig>,
+  pub hugo_dir: Option<PathBuf>,
+  pub hugo_posts_dir: PathBuf,
+  pub hugo_assets_dir: PathBuf,
+  /// Path of the site below its domain, like `/blog/` for `baseURL = "https://me.dev/blog/"`
+  pub base_path: String,
+  /// Url path of the posts dir, like `/posts/`, for links to notes found in no vault
+  pub posts_url: String,
+  /// Url path of the assets dir, like `/assets/`
+  pub assets_url: String,
+  /// Prop deciding whether a note is published
+  pub publish_key: String,
+  /// Accepted values of the publish prop
+  pub publish: Vec<String>,
+  /// Values of the publish prop exporting notes as Hugo drafts
+  pub publish_draft: Vec<String>,
+  /// Don't accept YAML booleans like `publish: true` besides `publish`
+  pub publish_strict: bool,
+  /// Tags of the notes to export
+  pub publish_tag: Vec<String>,
+  /// Which of the publish prop and `publish_tag` select the notes to export
+  pub publish_by: PublishBy,
+  /// Glob patterns of the notes to export, all notes if empty
+  pub include: Vec<String>,
+  /// Glob patterns of the notes and dirs to skip
+  pub exclude: Vec<String>,
+  /// Vault folders exported to their own Hugo sub dir instead of the posts dir
+  pub sections: BTreeMap<PathBuf, PathBuf>,
+  /// Props of notes copied to their post under another name
+  pub rename_props: BTreeMap<String, String>,
+  /// Props of notes never written into their post
+  pub exclude_props: Vec<String>,
+  /// Frontmatter format of the posts
+  pub front_matter_format: FrontMatterFormat,
+  /// Tera template writing the frontmatter of posts instead of `front_matter_format`
+  pub front_matter_template: Option<PathBuf>,
+  /// Props of notes, `mtime`, `git` or `filename`, giving the `date` of their post
+  pub date_from: Vec<String>,
+  /// Props of notes, `mtime`, `git` or `filename`, giving the `lastmod` of their post
+  pub lastmod_from: Vec<String>,
+  /// Format of the dates starting the names of daily notes
+  pub filename_date_format: String,
+  /// Format of the dates of posts, RFC 3339 if not set
+  pub date_format: Option<String>,
+  /// Timezone of the dates of posts: `utc`, `local` or an offset like `+02:00`
+  pub timezone: String,
+  /// Generate a description of this length from the first paragraph of notes without one
+  pub description_length: Option<usize>,
+  /// Write the cover image of posts as `cover: {image: ...}`, for PaperMod like themes
+  pub cover_map: bool,
+  /// Set `math: true` on the posts of notes with math, for themes to load KaTeX or MathJax
+  pub math_prop: bool,
+  /// Add the `#tags` written in notes to the `tags` of their posts
+  pub inline_tags_prop: bool,
+  /// What nested tags like `area/topic` become
+  pub nested_tags: NestedTags,
+  /// Authors of the posts of notes without an `author` prop
+  pub author: Vec<String>,
+  /// Hugo taxonomies besides `tags`, by name
+  pub taxonomies: BTreeMap<String, TaxonomyConfig>,
+  /// Props given to all notes beneath a vault folder, by folder
+  pub cascade: BTreeMap<PathBuf, BTreeMap<String, Value>>,
+  /// Depth of the notes embedded with `![[Note]]` whose content is inlined, 0 to only link them
+  pub embed_depth: usize,
+  /// Levels the headings of notes are demoted by, `# Heading` becoming `## Heading` with 1
+  pub shift_headings: usize,
+  /// Line starting the public content of a note again after an end marker, or from the top
+  pub start_marker: String,
+  /// Line ending the public content of a note, until a start marker
+  pub end_marker: String,
+  /// Line ending the summary of a post, written as Hugo's `<!--more-->`
+  pub more_marker: String,
+  /// What private blocks are replaced with, left out if not set
+  pub private_placeholder: Option<String>,
+  /// Which embedded images are written as Hugo `figure` shortcodes
+  pub figure_shortcode: FigureShortcode,
+  /// Write embedded images with an alt text as `figure` shortcodes captioned with it
+  pub figure_caption: bool,
+  /// What callouts like `> [!note] Title` become
+  pub callouts: Callouts,
+  /// Name of the shortcode callouts are written as
+  pub callout_shortcode: String,
+  /// What links to unpublished notes become, `{text}` standing for the text of the link
+  pub unpublished_link: String,
+  /// What `==highlights==` become, `{text}` standing for the highlighted text
+  pub highlight: String,
+  /// What ```` ```mermaid ```` code blocks become, `{code}` standing for the diagram, kept if not set
+  pub mermaid: Option<String>,
+  /// What the urls of videos on their own line become, by url pattern, `{id}` standing for the video
+  pub videos: BTreeMap<String, String>,
+  /// What tasks like `- [ ] Todo` become
+  pub tasks: Tasks,
+  /// What the HTML written in notes becomes
+  pub raw_html: RawHtml,
+  /// What the `#tags` written in notes become
+  pub inline_tags: InlineTags,
+  /// What Dataview queries become
+  pub dataview: Dataview,
+  /// What Dataview query blocks are replaced with, `{code}` standing for the query
+  pub dataview_placeholder: String,
+  /// How math is delimited in posts
+  pub math_delimiters: MathDelimiters,
+  /// How letters of other scripts than latin are written in the urls of posts and assets
+  pub slug_unicode: SlugUnicode,
+  /// Whether letters of other scripts than latin are written with latin letters in slugs
+  pub slug_transliterate: SlugTransliterate,
+  /// What happens when notes have the same slug
+  pub slug_collision: SlugCollision,
+  /// Add the old url of a note renamed while watching to the `aliases` of its post
+  pub rename_aliases: bool,
+  /// Export notes into Hugo sections mirroring their folders, instead of prefixing their slugs
+  pub mirror_folders: bool,
+  /// Where posts list the posts linking to them
+  pub backlinks: Backlinks,
+  /// Heading of the section listing the posts linking to a post
+  pub backlinks_title: String,
+  /// Write the posts and their links into `data/graph.json` of the Hugo dir
+  pub graph: bool,
+  /// Move deleted and overwritten dst files to the trash instead of removing them
+  pub trash: bool,
+  /// Dir backing the dst dirs up before a clean or forced export, relative to the Hugo dir
+  pub backup_dir: Option<PathBuf>,
+  /// Number of backups to keep
+  pub backup_keep: usize,
+}
+
+impl Config {
+  /// Load the config file (if any) and override its values with CLI flags.
+  /// Returns the selected profiles, all of them if none is selected.
+  pub fn load(args: GlobalArgs) -> Result<Vec<Self>> {
+    let mut file = load_config_file(&args)?;
+
+    let mut profiles = std::mem::take(&mut file.profiles);
+    if profiles.is_empty() {
+      if let Some(name) = args.profile.first() {
+        bail!("Profile `{name}` is not defined in the config file");
+      }
+      return Ok(vec![Self::resolve(&args, file, None)]);
+    }
+
+    let names: Vec<String> = if args.profile.is_empty() {
+      profiles.keys().cloned().collect()
+    } else {
+      args.profile.clone()
+    };
+    let mut configs = vec![];
+    for name in names {
+      let Some(profile) = profiles.remove(&name) else {
+        bail!("Profile `{name}` is not defined in the config file");
+      };
+      configs.push(Self::resolve(&args, profile.overlay(&file), Some(name)));
+    }
+    Ok(configs)
+  }
+
+  fn resolve(args: &GlobalArgs, file: ConfigFile, name: Option<String>) -> Self {
+    // Vaults given on the command line replace the ones of the config file
+    let vaults = if args.obsidian_dir.is_empty() {
+      file
+        .obsidian_dir
+        .map(VaultConfig::new)
+        .into_iter()
+        .chain(file.vaults)
+        .collect()
+    } else {
+      args
+        .obsidian_dir
+        .iter()
+        .cloned()
+        .map(VaultConfig::new)
+        .collect()
+    };
+
+    let publish_tag = or_base(args.publish_tag.clone(), &file.publish_tag);
+    let publish_by = args
+      .publish_by
+      .or(file.publish_by)
+      .unwrap_or(if publish_tag.is_empty() {
+        PublishBy::Prop
+      } else {
+        PublishBy::Either
+      });
+
+    let mut date_from = or_base(
+      file.date_from,
+      &strings(&["date", "created", "published", "filename", "mtime"]),
+    );
+    let mut lastmod_from = or_base(
+      file.lastmod_from,
+      &strings(&["lastmod", "updated", "modified", "mtime"]),
+    );
+    // The commit dates are more stable than the mtime, which is left for uncommitted notes
+    if args.date_from_git || file.date_from_git.unwrap_or(false) {
+      for sources in [&mut date_from, &mut lastmod_from] {
+        if !sources.iter().any(|source| source == "git") {
+          let pos = sources
+            .iter()
+            .position(|source| source == "mtime")
+            .unwrap_or(sources.len());
+          sources.insert(pos, "git".to_string());
+        }
+      }
+    }
+
+    let hugo_posts_dir = args
+      .hugo_posts_dir
+      .clone()
+      .or(file.hugo_posts_dir)
+      .unwrap_or_else(|| PathBuf::from("content/posts"));
+    let hugo_assets_dir = args
+      .hugo_assets_dir
+      .clone()
+      .or(file.hugo_assets_dir)
+      .unwrap_or_else(|| PathBuf::from("content/assets"));
+    Self {
+      name,
+      vaults,
+      hugo_dir: args.hugo_dir.clone().or(file.hugo_dir),
+      base_path: url_dir(file.base_path.as_deref().unwrap_or("/")),
+      posts_url: file
+        .posts_url
+        .as_deref()
+        .map_or_else(|| dir_url(&hugo_posts_dir), url_dir),
+      assets_url: file
+        .assets_url
+        .as_deref()
+        .map_or_else(|| dir_url(&hugo_assets_dir), url_dir),
+      hugo_posts_dir,
+      hugo_assets_dir,
+      publish_key: args
+        .publish_key
+        .clone()
+        .or(file.publish_key)
+        .unwrap_or_else(|| "publish".to_string()),
+      publish: or_base(
+        args.publish_value.clone(),
+        &file
+          .publish
+          .map_or_else(|| vec!["web".to_string()], Vec::from),
+      ),
+      publish_draft: or_base(
+        args.publish_draft.clone(),
+        &file
+          .publish_draft
+          .map_or_else(|| vec!["draft".to_string()], Vec::from),
+      ),
+      publish_strict: args.publish_strict || file.publish_strict.unwrap_or(false),
+      publish_tag,
+      publish_by,
+      include: or_base(args.include.clone(), &file.include),
+      exclude: or_base(args.exclude.clone(), &file.exclude),
+      sections: file.sections,
+      rename_props: file.rename_props,
+      exclude_props: file.exclude_props,
+      front_matter_format: args
+        .front_matter_format
+        .or(file.front_matter_format)
+        .unwrap_or(FrontMatterFormat::Yaml),
+      front_matter_template: file.front_matter_template,
+      date_from,
+      lastmod_from,
+      filename_date_format: file
+        .filename_date_format
+        .unwrap_or_else(|| "[year]-[month]-[day]".to_string()),
+      date_format: file.date_format,
+      timezone: file.timezone.unwrap_or_else(|| "utc".to_string()),
+      description_length: file.description_length,
+      cover_map: file.cover_map.unwrap_or(false),
+      math_prop: file.math_prop.unwrap_or(true),
+      inline_tags_prop: file.inline_tags_prop.unwrap_or(true),
+      nested_tags: file.nested_tags.unwrap_or_default(),
+      author: file.author.map(Vec::from).unwrap_or_default(),
+      taxonomies: file.taxonomies,
+      cascade: file.cascade,
+      embed_depth: file.embed_depth.unwrap_or(3),
+      shift_headings: args.shift_headings.or(file.shift_headings).unwrap_or(0),
+      start_marker: file
+        .start_marker
+        .unwrap_or_else(|| "=== start ===".to_string()),
+      end_marker: file.end_marker.unwrap_or_else(|| "=== end ===".to_string()),
+      more_marker: file
+        .more_marker
+        .unwrap_or_else(|| "=== more ===".to_string()),
+      private_placeholder: file.private_placeholder,
+      figure_shortcode: file.figure_shortcode.unwrap_or_default(),
+      figure_caption: file.figure_caption.unwrap_or(false),
+      callouts: file.callouts.unwrap_or_default(),
+      callout_shortcode: file
+        .callout_shortcode
+        .unwrap_or_else(|| "admonition".to_string()),
+      unpublished_link: file
+        .unpublished_link
+        .unwrap_or_else(|| "{text}".to_string()),
+      highlight: file
+        .highlight
+        .unwrap_or_else(|| "<mark>{text}</mark>".to_string()),
+      math_delimiters: file.math_delimiters.unwrap_or_default(),
+      mermaid: file.mermaid,
+      videos: file.videos.unwrap_or_else(|| {
+        DEFAULT_VIDEOS
+          .iter()
+          .map(|(url, embed)| (url.to_string(), embed.to_string()))
+          .collect()
+      }),
+      tasks: file.tasks.unwrap_or_default(),
+      raw_html: file.raw_html.unwrap_or_default(),
+      inline_tags: file.inline_tags.unwrap_or_default(),
+      dataview: file.dataview.unwrap_or_default(),
+      dataview_placeholder: file
+        .dataview_placeholder
+        .unwrap_or_else(|| "*This list is only available in the vault.*".to_string()),
+      slug_unicode: file.slug_unicode.unwrap_or_default(),
+      slug_transliterate: file.slug_transliterate.unwrap_or_default(),
+      slug_collision: file.slug_collision.unwrap_or_default(),
+      rename_aliases: args.rename_aliases || file.rename_aliases.unwrap_or(false),
+      mirror_folders: file.mirror_folders.unwrap_or(false),
+      backlinks: file.backlinks.unwrap_or_default(),
+      backlinks_title: file
+        .backlinks_title
+        .unwrap_or_else(|| "Linked from".to_string()),
+      graph: file.graph.unwrap_or(false),
+      trash: args.trash || file.trash.unwrap_or(false),
+      backup_dir: args.backup_dir.clone().or(file.backup_dir),
+      backup_keep: args.backup_keep.or(file.backup_keep).unwrap_or(5),
+    }
+  }
+}
+
+/// Names of the profiles defined in the config file
+pub fn profile_names(args: &GlobalArgs) -> Result<Vec<String>> {
+  Ok(load_config_file(args)?.profiles.into_keys().collect())
+}
+
+/// The config file given by `--config`, or the default one if present
+fn load_config_file(args: &GlobalArgs) -> Result<ConfigFile> {
+  match &args.config {
+    Some(path) => read_config_file(path),
+    None if Path::new(DEFAULT_CONFIG_FILE).is_file() => {
+      read_config_file(Path::new(DEFAULT_CONFIG_FILE))
+    }
+    None => Ok(ConfigFile::default()),
+  }
+}
+
+/// `path` with a slash at both ends, like `/blog/` for `blog`
+fn url_dir(path: &str) -> String {
+  match path.trim_matches('/') {
+    "" => "/".to_string(),
+    path => format!("/{path}/"),
+  }
+}
+
+/// The url path of a dir of the Hugo site, `/posts/` for `content/posts` or `/img/` for `static/img`
+fn dir_url(dir: &Path) -> String {
+  let dir = dir
+    .strip_prefix("content")
+    .or_else(|_| dir.strip_prefix("static"))
+    .unwrap_or(dir);
+  url_dir(&dir.to_string_lossy().replace('\\', "/"))
+}
+
+/// Owned `values`
+fn strings(values: &[&str]) -> Vec<String> {
+  values.iter().map(|value| value.to_string()).collect()
+}
+
+/// `values` if any, `base` otherwise
+fn or_base<T: Clone>(values: Vec<T>, base: &[T]) -> Vec<T> {
+  if values.is_empty() {
+    base.to_vec()
+  } else {
+    values
+  }
+}
+
+fn read_config_file(path: &Path) -> Result<ConfigFile> {
+  let text =
+    fs::read_to_string(path).with_context(|| format!("Cannot read config file {path:?}"))?;
+  let mut file: ConfigFile =
+    toml::from_str(&text).with_context(|| format!("Invalid config file {path:?}"))?;
+
+  // Relative dirs in the config file are relative to the config file itself
+  resolve_dirs(&mut file, path.parent().unwrap_or(Path::new("")));
+  for (name, profile) in file.profiles.iter_mut() {
+    if !profile.profiles.is_empty() {
+      bail!("Profile `{name}` cannot define nested profiles");
+    }
+    resolve_dirs(profile, path.parent().unwrap_or(Path::new("")));
+  }
+
+  Ok(file)
+}
+
+fn resolve_dirs(file: &mut ConfigFile, base: &Path) {
+  let vault_dirs = file.vaults.iter_mut().map(|vault| &mut vault.dir);
+  for dir in [
+    &mut file.obsidian_dir,
+    &mut file.hugo_dir,
+    &mut file.front_matter_template,
+  ]
+  .into_iter()
+  .flatten()
+  .chain(vault_dirs)
+  {
+    if dir.is_relative() {
+      *dir = base.join(&dir);
+    }
+  }
+}
+
+#[cfg(test)]
+impl Config {
+  /// The config of the TOML `text`, as if read from a config file with no flags given
+  pub fn from_toml(text: &str) -> Self {
+    use clap::Parser;
+    let cli = crate::Cli::parse_from(["tecexp", "pipe"]);
+    Self::resolve(&cli.args, toml::from_str(text).unwrap(), None)
+  }
+}
@@ -0,0 +1,2242 @@
+use std::{collections::BTreeMap, io::Write, ops::Range};
+
+use anyhow::Result;
+use serde_yaml::{Mapping, Value};
+use time::{macros::format_description, OffsetDateTime};
+
+use crate::{
+  config::{
+    Callouts, Config, Dataview, FigureShortcode, InlineTags, MathDelimiters, NestedTags, RawHtml,
+    Tasks, TermStyle,
+  },
+  date::parse_date,
+  git::GitDates,
+  props::{FrontMatterFormat, Prop, PropRules},
+  slug::SlugRules,
+};
+
+/// Dates of a note besides its props
+pub struct NoteDates<'a> {
+  pub modified: OffsetDateTime,
+  /// Dates of the first and last commits of the note, if committed
+  pub git: Option<&'a GitDates>,
+  /// Name of the note, which may start with its date
+  pub name: Option<&'a str>,
+}
+
+/// How the content of notes becomes the content of posts
+#[derive(Debug, Clone)]
+pub struct ContentRules {
+  /// Depth of the embedded notes whose content is inlined
+  pub embed_depth: usize,
+  /// Levels the headings of notes are demoted by
+  pub shift_headings: usize,
+  /// Line starting the public content of a note again
+  pub start_marker: String,
+  /// Line ending the public content of a note
+  pub end_marker: String,
+  /// Line ending the summary of a post
+  pub more_marker: String,
+  /// What private blocks are replaced with, left out if not set
+  pub private_placeholder: Option<String>,
+  /// Which embedded images are written as `figure` shortcodes
+  pub figure_shortcode: FigureShortcode,
+  /// Write images with an alt text as `figure` shortcodes captioned with it
+  pub figure_caption: bool,
+  /// What callouts like `> [!note] Title` become
+  pub callouts: Callouts,
+  /// Name of the shortcode callouts are written as
+  pub callout_shortcode: String,
+  /// What links to unpublished notes become, `{text}` standing for their text
+  pub unpublished_link: String,
+  /// What `==highlights==` become, `{text}` standing for their text
+  pub highlight: String,
+  /// What mermaid code blocks become, `{code}` standing for the diagram, kept if not set
+  pub mermaid: Option<String>,
+  /// What the urls of videos on their own line become, by url pattern, `{id}` standing for the video
+  pub videos: BTreeMap<String, String>,
+  /// What tasks like `- [ ] Todo` become
+  pub tasks: Tasks,
+  /// What the HTML written in notes becomes
+  pub raw_html: RawHtml,
+  /// What the `#tags` written in notes become
+  pub inline_tags: InlineTags,
+  /// What nested tags like `area/topic` become
+  pub nested_tags: NestedTags,
+  /// What Dataview queries become
+  pub dataview: Dataview,
+  /// What Dataview query blocks are replaced with, `{code}` standing for the query
+  pub dataview_placeholder: String,
+  /// How math is delimited in posts
+  pub math_delimiters: MathDelimiters,
+  /// How the names of notes and assets become urls
+  pub slug: SlugRules,
+}
+
+impl ContentRules {
+  pub fn new(config: &Config) -> Self {
+    Self {
+      embed_depth: config.embed_depth,
+      shift_headings: config.shift_headings,
+      start_marker: config.start_marker.clone(),
+      end_marker: config.end_marker.clone(),
+      more_marker: config.more_marker.clone(),
+      private_placeholder: config.private_placeholder.clone(),
+      figure_shortcode: config.figure_shortcode,
+      figure_caption: config.figure_caption,
+      callouts: config.callouts,
+      callout_shortcode: config.callout_shortcode.clone(),
+      unpublished_link: config.unpublished_link.clone(),
+      highlight: config.highlight.clone(),
+      mermaid: config.mermaid.clone(),
+      videos: config.videos.clone(),
+      tasks: config.tasks,
+      raw_html: config.raw_html,
+      inline_tags: config.inline_tags,
+      nested_tags: config.nested_tags,
+      dataview: config.dataview,
+      dataview_placeholder: config.dataview_placeholder.clone(),
+      math_delimiters: config.math_delimiters,
+      slug: SlugRules::new(config),
+    }
+  }
+
+  /// The template code blocks in `lang` are written with instead, `{code}` standing for their code,
+  /// none to keep them
+  fn code_block_template(&self, lang: &str) -> Option<&str> {
+    match (lang, self.dataview) {
+      ("mermaid", _) => self.mermaid.as_deref(),
+      ("dataview" | "dataviewjs", Dataview::Keep) => None,
+      ("dataview" | "dataviewjs", Dataview::Strip) => Some(""),
+      ("dataview" | "dataviewjs", Dataview::Placeholder) => Some(&self.dataview_placeholder),
+      _ => None,
+    }
+  }
+}
+
+/// The delimiter of display math, on its own line around a block
+const MATH_FENCE: &str = "$$";
+
+/// Lines around a private block, comments hiding it in Obsidian's reading view too
+const PRIVATE_START: &str = "%%private%%";
+const PRIVATE_END: &str = "%%/private%%";
+
+/// Type of the callouts holding private blocks, like `> [!private]`
+const PRIVATE_CALLOUT: &str = "private";
+
+/// Scheme of the uris opening notes in the Obsidian app, like `obsidian://open?vault=V&file=Note`
+const OBSIDIAN_URI: &str = "obsidian://";
+
+/// Other names Obsidian gives callout types, and the type of admonition shortcodes they are
+const CALLOUT_ALIASES: [(&str, &str); 14] = [
+  ("summary", "abstract"),
+  ("tldr", "abstract"),
+  ("hint", "tip"),
+  ("important", "tip"),
+  ("check", "success"),
+  ("done", "success"),
+  ("help", "question"),
+  ("faq", "question"),
+  ("caution", "warning"),
+  ("attention", "warning"),
+  ("fail", "failure"),
+  ("missing", "failure"),
+  ("error", "danger"),
+  ("cite", "quote"),
+];
+
+/// Extensions of the images Obsidian embeds
+const IMAGE_EXTENSIONS: [&str; 8] = ["png", "jpg", "jpeg", "gif", "webp", "svg", "avif", "bmp"];
+
+/// Props of notes copied into their post as they are, ordering and navigation of Hugo
+const PASSTHROUGH_PROPS: [&str; 2] = ["weight", "menu"];
+
+/// Build the props of the post of a note, which inherits the `defaults` of its folders.
+/// `on_asset` is called with the name and the url name of the cover image, if it is in the vault.
+pub fn build_dst_props(
+  src_props: &BTreeMap<String, Prop>,
+  defaults: &BTreeMap<String, Prop>,
+  body: &[String],
+  rules: &PropRules,
+  title: Option<&str>,
+  dates: &NoteDates,
+  on_asset: &mut dyn FnMut(&str, &str) -> Result<()>,
+) -> Result<BTreeMap<String, Prop>> {
+  let mut props: BTreeMap<String, Prop> = BTreeMap::new();
+
+  // The props of the note win over the defaults, but its tags add up to theirs
+  let mut merged = defaults.clone();
+  merged.extend(src_props.clone());
+  if let (Some(default_tags), Some(tags)) = (defaults.get("tags"), src_props.get("tags")) {
+    let mut all_tags = default_tags.strings();
+    for tag in tags.strings() {
+      if !all_tags.contains(&tag) {
+        all_tags.push(tag);
+      }
+    }
+    merged.insert("tags".to_string(), Prop::Vec(all_tags));
+  }
+  // So do the tags written in the note, whatever their case
+  if rules.inline_tags_prop {
+    let mut all_tags = merged.get("tags").map(Prop::strings).unwrap_or_default();
+    let count = all_tags.len();
+    for tag in body_tags(body) {
+      let is_new = !all_tags
+        .iter()
+        .any(|other| other.trim_start_matches('#').to_lowercase() == tag.to_lowercase());
+      if is_new {
+        all_tags.push(tag);
+      }
+    }
+    if all_tags.len() > count {
+      merged.insert("tags".to_string(), Prop::Vec(all_tags));
+    }
+  }
+  // Nested tags like `area/topic` are cut to their leaf or split into tags, if asked
+  if let Some(tags) = merged
+    .get("tags")
+    .filter(|_| rules.nested_tags != NestedTags::Keep)
+  {
+    let mut flat_tags: Vec<String> = vec![];
+    for tag in tags
+      .strings()
+      .iter()
+      .flat_map(|tag| rules.nested_tags.apply(tag))
+    {
+      if !flat_tags.contains(&tag) {
+        flat_tags.push(tag);
+      }
+    }
+    merged.insert("tags".to_string(), Prop::Vec(flat_tags));
+  }
+  let src_props = &merged;
+
+  // The title prop only changes the displayed title, the slug stays the one of the file name
+  let title_prop = src_props
+    .get("title")
+    .and_then(|title| title.strings().into_iter().next());
+  if let Some(title) = title_prop.or(title.map(str::to_string)) {
+    props.insert("title".to_string(), Prop::Str(title));
+  }
+
+  let git_first = dates.git.map(|git| &git.first);
+  let git_last = dates.git.map(|git| &git.last);
+  for (key, sources, git) in [
+    ("date", &rules.date_from, git_first),
+    ("lastmod", &rules.lastmod_from, git_last),
+  ] {
+    if let Some(date) = find_date(sources, src_props, rules, dates, git) {
+      props.insert(key.to_string(), Prop::Date(date));
+    }
+  }
+
+  // Hugo only builds the post between these dates
+  for (dst_key, src_keys) in [
+    ("publishDate", ["publish_at", "publishDate"]),
+    ("expiryDate", ["expires", "expiryDate"]),
+  ] {
+    let date = src_keys.iter().find_map(|key| match src_props.get(*key) {
+      Some(Prop::Date(date)) => Some(rules.format_written_date(date)),
+      _ => None,
+    });
+    if let Some(date) = date {
+      props.insert(dst_key.to_string(), Prop::Date(date));
+    }
+  }
+
+  // Search engines and list pages show the description
+  let summary = || Some(Prop::Str(summarize(body, rules.description_length?)?));
+  if let Some(description) = src_props.get("description").cloned().or_else(summary) {
+    props.insert("description".to_string(), description);
+  }
+
+  // Themes only load KaTeX or MathJax for posts asking for it
+  let math = || (rules.math_prop && has_math(body)).then_some(Prop::Bool(true));
+  if let Some(math) = src_props.get("math").cloned().or_else(math) {
+    props.insert("math".to_string(), math);
+  }
+
+  for key in PASSTHROUGH_PROPS {
+    if let Some(val) = src_props.get(key) {
+      props.insert(key.to_string(), val.clone());
+    }
+  }
+
+  // One author as a string, several as a list, for multi-author themes
+  let default_author = match rules.author.as_slice() {
+    [] => None,
+    [author] => Some(Prop::Str(author.clone())),
+    authors => Some(Prop::Vec(authors.to_vec())),
+  };
+  if let Some(author) = src_props.get("author").cloned().or(default_author) {
+    props.insert("author".to_string(), author);
+  }
+
+  if let Some((key, cover)) = cover_prop(src_props, rules, on_asset)? {
+    props.insert(key, cover);
+  }
+
+  if rules.is_draft(src_props) {
+    props.insert("draft".to_string(), Prop::Bool(true));
+  }
+
+  if !rules.rename.contains_key("tags") {
+    if let Some(tags) = src_props.get("tags") {
+      props.insert("tags".to_string(), tags.clone());
+    }
+  }
+
+  for (name, taxonomy) in &rules.taxonomies {
+    let prop = taxonomy.prop.as_ref().unwrap_or(name);
+    if let Some(terms) = src_props.get(prop).map(Prop::strings) {
+      let terms = terms
+        .iter()
+        .map(|term| {
+          let term = term.trim().trim_start_matches('#');
+          match taxonomy.terms {
+            TermStyle::Keep => term.to_string(),
+            TermStyle::Lower => term.to_lowercase(),
+            TermStyle::Slug => rules.slug.to_url(term),
+          }
+        })
+        .collect();
+      props.insert(name.clone(), Prop::Vec(terms));
+    }
+  }
+
+  // Defaults tecexp has no rule for, like `section` or `type`, go into the post as they are
+  for (key, val) in defaults {
+    if *key != rules.publish_key && !rules.rename.contains_key(key) {
+      props.entry(key.clone()).or_insert_with(|| val.clone());
+    }
+  }
+
+  // Renamed props win over the ones tecexp sets
+  for (src_key, dst_key) in &rules.rename {
+    if rules.exclude.contains(src_key) {
+      continue;
+    }
+    if let Some(val) = src_props.get(src_key) {
+      props.insert(dst_key.clone(), val.clone());
+    }
+  }
+
+  props.retain(|key, _| !rules.exclude.contains(key));
+  Ok(props)
+}
+
+/// The `cover` or `image` prop, pointing to the url of the image when it is in the vault
+fn cover_prop(
+  src_props: &BTreeMap<String, Prop>,
+  rules: &PropRules,
+  on_asset: &mut dyn FnMut(&str, &str) -> Result<()>,
+) -> Result<Option<(String, Prop)>> {
+  let Some((key, prop)) = ["cover", "image"]
+    .into_iter()
+    .find_map(|key| Some((key, src_props.get(key)?)))
+  else {
+    return Ok(None);
+  };
+  // `cover: {image: x.png, alt: ...}` of PaperMod like themes
+  let (mut map, image) = match prop {
+    Prop::Str(image) => (Mapping::new(), image.as_str()),
+    Prop::Nested(Value::Mapping(map)) => match map.get("image").and_then(Value::as_str) {
+      Some(image) => (map.clone(), image),
+      None => return Ok(Some((key.to_string(), prop.clone()))),
+    },
+    _ => return Ok(Some((key.to_string(), prop.clone()))),
+  };
+
+  // Obsidian writes `[[x.png]]` when picking a file
+  let name = image
+    .trim_start_matches('!')
+    .trim_start_matches("[[")
+    .trim_end_matches("]]");
+  let name = name.split('|').next().unwrap_or(name);
+  let url = if name.contains("://") || name.starts_with('/') {
+    name.to_string()
+  } else {
+    let url = rules.slug.to_url(name);
+    on_asset(name, &url)?;
+    rules.slug.asset_url(&url)
+  };
+
+  if map.is_empty() && !rules.cover_map {
+    return Ok(Some((key.to_string(), Prop::Str(url))));
+  }
+  map.insert("image".into(), url.into());
+  Ok(Some((
+    "cover".to_string(),
+    Prop::Nested(Value::Mapping(map)),
+  )))
+}
+
+/// The date of the first of the `sources` the note has, a date prop, `mtime`, `git` or `filename`
+fn find_date(
+  sources: &[String],
+  src_props: &BTreeMap<String, Prop>,
+  rules: &PropRules,
+  dates: &NoteDates,
+  git: Option<&String>,
+) -> Option<String> {
+  sources.iter().find_map(|source| match source.as_str() {
+    "mtime" => Some(rules.format_date(dates.modified.replace_nanosecond(0).ok()?)),
+    "git" => Some(rules.format_date(parse_date(git?, rules.timezone)?)),
+    "filename" => {
+      let date = rules.filename_date(dates.name?)?;
+      let date = date
+        .format(format_description!("[year]-[month]-[day]"))
+        .ok()?;
+      Some(rules.format_written_date(&date))
+    }
+    key => match src_props.get(key) {
+      Some(Prop::Date(date)) => Some(rules.format_written_date(date)),
+      _ => None,
+    },
+  })
+}
+
+/// The first paragraph of the note body as plain text, cut to `max_len` chars at a word boundary
+pub fn summarize(body: &[String], max_len: usize) -> Option<String> {
+  let mut paragraph = vec![];
+  let mut is_coding = false;
+  let mut is_comment = false;
+  for line in body {
+    let line = if is_coding {
+      line.clone()
+    } else {
+      strip_comments(line, &mut is_comment)
+    };
+    let line = line.trim();
+    if line.starts_with("```") {
+      is_coding = !is_coding;
+      continue;
+    }
+    if is_coding {
+      continue;
+    }
+    // Headings, embeds, tables, quotes and html are no prose
+    let is_prose = !line.is_empty() && !line.starts_with(['#', '!', '|', '>', '<', '%']);
+    if is_prose {
+      paragraph.push(plain_text(line));
+    } else if !paragraph.is_empty() {
+      break;
+    }
+  }
+
+  let text = paragraph.join(" ");
+  let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+  if text.is_empty() {
+    return None;
+  }
+  if text.chars().count() <= max_len {
+    return Some(text);
+  }
+  let cut: String = text.chars().take(max_len).collect();
+  let cut = cut
+    .rsplit_once(' ')
+    .map_or(cut.as_str(), |(words, _)| words);
+  Some(format!(
+    "{}…",
+    cut.trim_end_matches(|c: char| c.is_ascii_punctuation())
+  ))
+}
+
+/// A md line without links, embeds and emphasis markers
+fn plain_text(line: &str) -> String {
+  let mut text = String::new();
+  let mut rest = line;
+  while let Some(start) = rest.find('[') {
+    let embed = rest[..start].ends_with('!');
+    text.push_str(&rest[..start - usize::from(embed)]);
+    let after = &rest[start..];
+    if let Some(end) = after.strip_prefix("[[").and_then(|inner| inner.find("]]")) {
+      // `[[Note|Label]]` shows `Label`, `[[Note#Heading]]` shows `Note`
+      let inner = &after[2..2 + end];
+      if !embed {
+        let label = inner.rsplit_once('|').map_or(inner, |(_, label)| label);
+        text.push_str(label.split('#').next().unwrap_or(label));
+      }
+      rest = &after[2 + end + 2..];
+    } else if let Some((label, url_end)) = after[1..]
+      .split_once("](")
+      .and_then(|(label, url)| Some((label, url.find(')')?)))
+    {
+      if !embed {
+        text.push_str(label);
+      }
+      rest = &after[1 + label.len() + 2 + url_end + 1..];
+    } else {
+      text.push('[');
+      rest = &after[1..];
+    }
+  }
+  text.push_str(rest);
+  ["**", "__", "==", "~~", "`", "*"]
+    .iter()
+    .fold(text, |text, marker| text.replace(marker, ""))
+}
+
+/// Write `props` as the frontmatter of the dst md, quoting the values where needed
+pub fn write_props(
+  writer: &mut dyn Write,
+  props: &BTreeMap<String, Prop>,
+  format: FrontMatterFormat,
+) -> Result<()> {
+  match format {
+    FrontMatterFormat::Yaml => {
+      writeln!(writer, "---")?;
+      if !props.is_empty() {
+        write!(writer, "{}", serde_yaml::to_string(props)?)?;
+      }
+      writeln!(writer, "---")?;
+    }
+    FrontMatterFormat::Toml => {
+      writeln!(writer, "+++")?;
+      let mut table = toml::Table::new();
+      for (key, val) in props {
+        table.insert(key.clone(), val.to_toml()?);
+      }
+      write!(writer, "{}", toml::to_string(&table)?)?;
+      writeln!(writer, "+++")?;
+    }
+    FrontMatterFormat::Json => {
+      writeln!(writer, "{}", serde_json::to_string_pretty(props)?)?;
+    }
+  }
+  Ok(())
+}
+
+/// Write the content `lines` of a note, converting Obsidian syntax to Hugo.
+/// `on_asset` is called with the name and the url name of every referenced asset,
+/// `on_link` with the name of every linked note, giving the url of its post if it is published,
+/// `on_embed` with the name of every embedded note, giving its content if it is published.
+pub fn write_content(
+  writer: &mut dyn Write,
+  lines: impl Iterator<Item = String>,
+  rules: &ContentRules,
+  on_asset: &mut dyn FnMut(&str, &str) -> Result<()>,
+  on_link: &mut dyn FnMut(&str) -> Result<Option<String>>,
+  on_embed: &mut dyn FnMut(&str) -> Result<Option<Vec<String>>>,
+) -> Result<()> {
+  let mut hooks = Hooks {
+    on_asset,
+    on_link,
+    on_embed,
+    embeds: 0,
+  };
+  write_lines(writer, lines, rules, &mut hooks, &mut vec![])
+}
+
+/// The `lines` of a note without its private parts, from the end marker to the start marker,
+/// and its private blocks, between `%%private%%` and `%%/private%%` or in a `> [!private]` callout.
+/// A note whose first marker is a start marker is private from the top.
+pub fn public_lines(lines: Vec<String>, rules: &ContentRules) -> Vec<String> {
+  let is_marker = |line: &String, marker: &str| line.trim() == marker;
+  let first_marker = lines
+    .iter()
+    .find(|line| is_marker(line, &rules.start_marker) || is_marker(line, &rules.end_marker));
+  let mut is_private = first_marker.is_some_and(|line| is_marker(line, &rules.start_marker));
+  let mut public = Vec::with_capacity(lines.len());
+  let mut is_private_block = false;
+  let mut is_private_callout = false;
+  for line in lines {
+    if is_private_block {
+      is_private_block = line.trim() != PRIVATE_END;
+      continue;
+    }
+    if is_private_callout && strip_quote(&line).is_some() {
+      continue;
+    }
+    is_private_block = line.trim() == PRIVATE_START;
+    is_private_callout = parse_callout(&line).is_some_and(|callout| {
+      callout.kind == PRIVATE_CALLOUT && callout.quote.matches('>').count() == 1
+    });
+    if is_private_block || is_private_callout {
+      if let Some(placeholder) = rules.private_placeholder.as_ref().filter(|_| !is_private) {
+        public.push(placeholder.clone());
+      }
+      continue;
+    }
+    if is_marker(&line, &rules.start_marker) {
+      is_private = false;
+    } else if is_marker(&line, &rules.end_marker) {
+      is_private = true;
+    } else if !is_private {
+      public.push(line);
+    }
+  }
+  public
+}
+
+/// The callbacks of `write_content`
+struct Hooks<'a> {
+  on_asset: &'a mut dyn FnMut(&str, &str) -> Result<()>,
+  on_link: &'a mut dyn FnMut(&str) -> Result<Option<String>>,
+  on_embed: &'a mut dyn FnMut(&str) -> Result<Option<Vec<String>>>,
+  /// Number of notes inlined so far, prefixing the labels of their footnotes
+  embeds: usize,
+}
+
+/// Write the content `lines` of a note embedded in the notes of `embedding`, by url name
+fn write_lines(
+  writer: &mut dyn Write,
+  lines: impl Iterator<Item = String>,
+  rules: &ContentRules,
+  hooks: &mut Hooks,
+  embedding: &mut Vec<String>,
+) -> Result<()> {
+  // The fence of the code or `$$` math block the line is in
+  let mut fence: Option<String> = None;
+  let mut is_indented_code = false;
+  let mut is_list = false;
+  let mut is_blank = true;
+  let mut is_table = false;
+  // Number of callouts written as shortcodes the line is in
+  let mut callout_depth = 0;
+  // Whether a `%%` comment opened above goes on
+  let mut is_comment = false;
+  // Name of the unsafe HTML element opened above, left out up to its end tag
+  let mut unsafe_element: Option<String> = None;
+  let brackets = rules.math_delimiters == MathDelimiters::Brackets;
+  // The lines of the mermaid or Dataview block the line is in, and the template it is written with
+  let mut template_block: Option<(Vec<String>, &str)> = None;
+  // Footnotes of inlined notes are prefixed, not to clash with those of the note embedding them
+  let footnote_prefix = if embedding.is_empty() {
+    String::new()
+  } else {
+    format!("{}-", hooks.embeds)
+  };
+  // Definitions of the `^[inline footnotes]`, written after the note
+  let mut footnotes: Vec<String> = vec![];
+  let mut footnotes_written = 0;
+  // The indent of the task left out with the lines nested in it
+  let mut stripped_task: Option<usize> = None;
+  let mut lines = lines.fuse();
+  loop {
+    let line = match lines.next() {
+      Some(line) => line,
+      None if footnotes_written < footnotes.len() => {
+        footnotes_written += 1;
+        footnotes[footnotes_written - 1].clone()
+      }
+      None => break,
+    };
+
+    // The lines of callouts written as shortcodes lose their `>`, a line out of the quote closes them
+    let mut line = line;
+    let mut depth = 0;
+    while depth < callout_depth {
+      match strip_quote(&line) {
+        Some(inner) => line = inner.to_string(),
+        None => break,
+      }
+      depth += 1;
+    }
+    if depth < callout_depth {
+      fence = None;
+      if let Some((code, template)) = template_block.take() {
+        write_code_block(writer, template, &code)?;
+      }
+      for _ in depth..callout_depth {
+        writeln!(writer, "{{{{< /{} >}}}}", rules.callout_shortcode)?;
+      }
+      callout_depth = depth;
+    }
+
+    // Ignore coding and math blocks
+    if let Some(open) = &fence {
+      let is_math = open == MATH_FENCE;
+      let closes = closes_fence(open, &line);
+      if let Some((code, template)) = &mut template_block {
+        if closes {
+          write_code_block(writer, template, code)?;
+          template_block = None;
+          fence = None;
+        } else {
+          code.push(line);
+        }
+        continue;
+      }
+      match line.trim_end().strip_suffix(MATH_FENCE) {
+        Some(math) if is_math && closes && brackets => writeln!(writer, "{math}\\]")?,
+        _ => writeln!(writer, "{line}")?,
+      }
+      if closes {
+        fence = None;
+      }
+      continue;
+    }
+    // Comments and HTML are left as they are in indented code blocks, told apart as below
+    let is_code = !is_comment
+      && unsafe_element.is_none()
+      && (is_indented_code || (is_blank && !is_list))
+      && indent_width(&line) >= 4;
+    // `%%comments%%` are private to the vault, lines only holding comments are dropped
+    if !is_code && (is_comment || line.contains("%%")) {
+      let was_comment = is_comment;
+      let text = strip_comments(&line, &mut is_comment);
+      if text.trim().is_empty() && (was_comment || !line.trim().is_empty()) {
+        continue;
+      }
+      // The text after a comment closing on the line starts it
+      line = if was_comment {
+        text.trim_start().to_string()
+      } else {
+        text
+      };
+    }
+    // The HTML of notes is kept, stripped or sanitized as configured, lines only holding it dropped
+    let has_html = unsafe_element.is_some() || line.contains('<');
+    if !is_code && rules.raw_html != RawHtml::Keep && has_html {
+      let was_unsafe = unsafe_element.is_some();
+      let text = rewrite_html(&line, rules.raw_html, &mut unsafe_element);
+      if text.trim().is_empty() && (was_unsafe || !line.trim().is_empty()) {
+        continue;
+      }
+      line = if was_unsafe {
+        text.trim_start().to_string()
+      } else {
+        text
+      };
+    }
+    if let Some(open) = code_fence(&line).or_else(|| math_fence(&line)) {
+      if open == MATH_FENCE && brackets {
+        writeln!(writer, "{}", line.replacen(MATH_FENCE, "\\[", 1))?;
+      } else if let Some(template) = rules.code_block_template(line.trim()[open.len()..].trim()) {
+        template_block = Some((vec![], template));
+      } else {
+        writeln!(writer, "{line}")?;
+      }
+      fence = Some(open.to_string());
+      continue;
+    }
+    // The summary of the post on list pages ends there, notes embedded have none of their own
+    if line.trim() == rules.more_marker {
+      if embedding.is_empty() {
+        writeln!(writer, "<!--more-->")?;
+      }
+      continue;
+    }
+    // Lines indented by 4 spaces after a blank line are code, unless they continue a list item
+    let was_blank = is_blank;
+    is_blank = line.trim().is_empty();
+    if is_blank {
+      is_table = false;
+      writeln!(writer, "{line}")?;
+      continue;
+    }
+    let indent = indent_width(&line);
+    is_indented_code = indent >= 4 && (is_indented_code || (was_blank && !is_list));
+    if is_indented_code {
+      writeln!(writer, "{line}")?;
+      continue;
+    }
+    match stripped_task {
+      Some(task_indent) if indent > task_indent => continue,
+      _ => stripped_task = None,
+    }
+    // Themes write the title as the `h1`, headings go below it, down to the last level
+    if let Some(level) = heading_level(&line).filter(|_| rules.shift_headings > 0) {
+      let shifted = (level + rules.shift_headings).min(6);
+      line = format!("{}{}", "#".repeat(shifted), &line[level..]);
+    }
+    if let Some(callout) = parse_callout(&line) {
+      match rules.callouts {
+        Callouts::Shortcode if callout.quote.matches('>').count() == 1 => {
+          write_callout_open(writer, &callout, &rules.callout_shortcode)?;
+          callout_depth += 1;
+          continue;
+        }
+        Callouts::Shortcode | Callouts::Blockquote => {
+          line = format!("{}**{}**", callout.quote, callout.title());
+        }
+        Callouts::Keep => {}
+      }
+    }
+    if is_list_item(&line) {
+      is_list = true;
+    } else if indent == 0 {
+      is_list = false;
+    }
+    if let Some((checkbox, status)) = task_checkbox(&line) {
+      let is_done = matches!(status, 'x' | 'X');
+      match rules.tasks {
+        Tasks::Keep => {}
+        Tasks::Checkbox => {
+          let input = if is_done {
+            "<input type=\"checkbox\" disabled checked>"
+          } else {
+            "<input type=\"checkbox\" disabled>"
+          };
+          line.replace_range(checkbox, input);
+        }
+        Tasks::Strip if is_done => {}
+        Tasks::Strip => {
+          stripped_task = Some(indent);
+          continue;
+        }
+      }
+    }
+
+    // A note embedded on its own line is inlined
+    let embed = line
+      .trim()
+      .strip_prefix("![[")
+      .and_then(|rest| rest.strip_suffix("]]"))
+      .filter(|inner| !inner.contains("]]") && !is_asset(inner));
+    if let Some(inner) = embed {
+      write_embed(writer, inner, rules, hooks, embedding)?;
+      continue;
+    }
+    // A video linked on its own line is embedded, like Obsidian does with `![](url)`
+    if let Some(video) = video_embed(line.trim(), &rules.videos) {
+      writeln!(
+        writer,
+        "{}{video}",
+        &line[..line.len() - line.trim_start().len()]
+      )?;
+      continue;
+    }
+
+    // Rows follow the delimiter row of a table, a row without leading pipe may head it
+    is_table = is_table || is_table_delimiter(&line);
+    let is_table_row = is_table || line.trim_start().starts_with('|');
+
+    // Write line by line, into a buffer when math delimiters are rewritten after the links
+    let mut buf = vec![];
+    let out: &mut dyn Write = if brackets { &mut buf } else { &mut *writer };
+    let (line, block_id) = split_block_id(&line);
+    // The definitions of inline footnotes already have their label
+    let line = if footnotes_written == 0 {
+      rewrite_footnotes(line, &footnote_prefix, &mut footnotes)
+    } else {
+      line.to_string()
+    };
+    let line = match rules.dataview {
+      Dataview::Keep => line,
+      Dataview::Strip | Dataview::Placeholder => strip_inline_queries(&line),
+    };
+    let line = match rules.inline_tags {
+      InlineTags::Keep => line,
+      InlineTags::Strip | InlineTags::Link => rewrite_tags(&line, rules),
+    };
+    // Lines only holding tags are dropped with them
+    if line.trim().is_empty() && block_id.is_none() && !is_blank {
+      continue;
+    }
+    let line = &rewrite_highlights(&rewrite_md_links(&line), &rules.highlight);
+    let mut spans = code_spans(line);
+    spans.extend(math_spans(line, &spans));
+    let mut curr = 0;
+    // Replace `[[Some title]]` to `[Some title](/posts/some-title/)`
+    // Replace `![[some-img.png]]` to `![some-img.png](/assets/some-img.png)`
+    while let Some(start) = line[curr..].find("[[") {
+      // Code spans and math are written as they are
+      if let Some(span) = spans.iter().find(|span| span.contains(&(curr + start))) {
+        write!(out, "{}", &line[curr..span.end])?;
+        curr = span.end;
+        continue;
+      }
+      let before = &line[curr..(curr + start)];
+      // `\[[text]]` is no link, written without the backslash
+      if let Some(text) = before.strip_suffix('\\') {
+        write!(out, "{text}[[")?;
+        curr += start + 2;
+        continue;
+      }
+      curr += start;
+      if let Some(end) = line[(curr + 2)..].find("]]") {
+        let inner = &line[(curr + 2)..(curr + 2 + end)];
+        // Notes embedded within a line are linked
+        let (before, embed) = match before.strip_suffix('!') {
+          Some(text) => (text, true),
+          None => (before, false),
+        };
+        write!(out, "{before}")?;
+        if is_table_row {
+          // Cells escape the pipe of `[[Note\|Alias]]`, a pipe in the link would split the cell
+          let mut cell = vec![];
+          let inner = inner.replace("\\|", "|");
+          write_wikilink(
+            &mut cell,
+            &inner,
+            embed,
+            rules,
+            hooks.on_asset,
+            hooks.on_link,
+          )?;
+          write!(
+            out,
+            "{}",
+            String::from_utf8_lossy(&cell).replace('|', "\\|")
+          )?;
+        } else {
+          write_wikilink(out, inner, embed, rules, hooks.on_asset, hooks.on_link)?;
+        }
+        curr += 2 + end + 2;
+      } else {
+        write!(out, "{before}{}", &line[curr..])?;
+        curr = line.len();
+      }
+    }
+    write!(out, "{}", &line[curr..])?;
+    // The anchor `[[Note#^id]]` links to, dropped by Hugo unless it renders raw HTML
+    match block_id {
+      Some(id) if line.is_empty() => writeln!(out, "<span id=\"{}\"></span>", block_anchor(id))?,
+      Some(id) => writeln!(out, " <span id=\"{}\"></span>", block_anchor(id))?,
+      None => writeln!(out)?,
+    }
+    if brackets {
+      write!(writer, "{}", bracket_math(&String::from_utf8_lossy(&buf)))?;
+    }
+  }
+  if let Some((code, template)) = template_block {
+    write_code_block(writer, template, &code)?;
+  }
+  for _ in 0..callout_depth {
+    writeln!(writer, "{{{{< /{} >}}}}", rules.callout_shortcode)?;
+  }
+  Ok(())
+}
+
+/// Write the code block of the lines `code` with `template`, `{code}` standing for them,
+/// nothing if the template is empty
+fn write_code_block(writer: &mut dyn Write, template: &str, code: &[String]) -> Result<()> {
+  if !template.is_empty() {
+    writeln!(writer, "{}", template.replace("{code}", &code.join("\n")))?;
+  }
+  Ok(())
+}
+
+/// Write the note embedded by `![[inner]]`, its content unless it is embedded too deep
+/// or in itself, a callout linking to it otherwise
+fn write_embed(
+  writer: &mut dyn Write,
+  inner: &str,
+  rules: &ContentRules,
+  hooks: &mut Hooks,
+  embedding: &mut Vec<String>,
+) -> Result<()> {
+  let target = inner.split('|').next().unwrap_or(inner).trim();
+  let (note, section) = match target.split_once('#') {
+    Some((note, section)) => (note.trim(), Some(section)),
+    None => (target, None),
+  };
+  let key = rules.slug.to_url(note);
+  if !note.is_empty() && embedding.len() < rules.embed_depth && !embedding.contains(&key) {
+    let lines = (hooks.on_embed)(note)?.map(|lines| public_lines(lines, rules));
+    let lines = match section {
+      Some(section) => lines.and_then(|lines| section_lines(lines, section)),
+      None => lines,
+    };
+    // Blank lines keep the paragraphs around from running into the embedded ones
+    if let Some(lines) = lines {
+      (hooks.on_link)(note)?;
+      hooks.embeds += 1;
+      embedding.push(key);
+      writeln!(writer)?;
+      write_lines(writer, lines.into_iter(), rules, hooks, embedding)?;
+      writeln!(writer)?;
+      embedding.pop();
+      return Ok(());
+    }
+  }
+
+  write!(writer, "> [!NOTE]\n> ")?;
+  write_wikilink(writer, inner, false, rules, hooks.on_asset, hooks.on_link)?;
+  writeln!(writer, "\n")?;
+  Ok(())
+}
+
+/// The lines of the `Heading` or `^block` section of a note, none if it has no such section
+fn section_lines(lines: Vec<String>, section: &str) -> Option<Vec<String>> {
+  let section = section.rsplit('#').next().unwrap_or(section).trim();
+  if let Some(id) = section.strip_prefix('^') {
+    let pos = lines
+      .iter()
+      .position(|line| split_block_id(line).1 == Some(id))?;
+    // An id on a line of its own follows the block, like a list or a table
+    let end = if split_block_id(&lines[pos]).0.trim().is_empty() {
+      lines[..pos]
+        .iter()
+        .rposition(|line| !line.trim().is_empty())
+        .map_or(0, |last| last + 1)
+    } else {
+      pos + 1
+    };
+    // The block starts after a blank line or a heading
+    let start = lines[..end]
+      .iter()
+      .rposition(|line| line.trim().is_empty() || heading_level(line).is_some())
+      .map_or(0, |before| before + 1);
+    return Some(lines[start..end].to_vec());
+  }
+
+  let level_of = heading_level;
+  let anchor = heading_anchor(section);
+  let start = lines.iter().position(|line| {
+    level_of(line).is_some() && heading_anchor(line.trim_start_matches('#')) == anchor
+  })?;
+  let level = level_of(&lines[start])?;
+  // Up to the next heading of the same or a higher level
+  let end = lines[start + 1..]
+    .iter()
+    .position(|line| level_of(line).is_some_and(|other| other <= level))
+    .map_or(lines.len(), |pos| start + 1 + pos);
+  Some(lines[start..end].to_vec())
+}
+
+/// Whether `line` closes the code block opened by the fence `open`
+fn closes_code_fence(open: &str, line: &str) -> bool {
+  code_fence(line).is_some_and(|close| {
+    close.starts_with(&open[..1]) && close.len() >= open.len() && line.trim() == close
+  })
+}
+
+/// Whether `line` closes the code or `$$` math block opened by the fence `open`
+fn closes_fence(open: &str, line: &str) -> bool {
+  if open == MATH_FENCE {
+    line.trim_end().ends_with(MATH_FENCE)
+  } else {
+    closes_code_fence(open, line)
+  }
+}
+
+/// The `#tags` written in the `body` of a note outside of code, math and comments, without `#`
+fn body_tags(body: &[String]) -> Vec<String> {
+  let mut tags = vec![];
+  let mut fence: Option<&str> = None;
+  let mut is_comment = false;
+  let mut is_indented_code = false;
+  let mut is_list = false;
+  let mut is_blank = true;
+  for line in body {
+    if let Some(open) = fence {
+      if closes_fence(open, line) {
+        fence = None;
+      }
+      continue;
+    }
+    fence = code_fence(line).or_else(|| math_fence(line));
+    if fence.is_some() {
+      continue;
+    }
+    // Indented code blocks are told apart as in `write_lines`
+    let was_blank = is_blank;
+    is_blank = line.trim().is_empty();
+    if is_blank {
+      continue;
+    }
+    let indent = indent_width(line);
+    is_indented_code = !is_comment && indent >= 4 && (is_indented_code || (was_blank && !is_list));
+    if is_indented_code {
+      continue;
+    }
+    if is_list_item(line) {
+      is_list = true;
+    } else if indent == 0 {
+      is_list = false;
+    }
+    let text = strip_comments(line, &mut is_comment);
+    for range in tag_ranges(&text) {
+      tags.push(text[range.start + 1..range.end].to_string());
+    }
+  }
+  tags
+}
+
+/// The byte ranges of the `#tags` of `line` outside of code and math, `#` included.
+/// Like in Obsidian, tags follow a space and hold letters, digits, `_`, `-` and `/`, not only digits.
+fn tag_ranges(line: &str) -> Vec<Range<usize>> {
+  let mut spans = code_spans(line);
+  spans.extend(math_spans(line, &spans));
+  let mut tags = vec![];
+  for (i, _) in line.match_indices('#') {
+    let is_after_space = line[..i]
+      .chars()
+      .next_back()
+      .is_none_or(char::is_whitespace);
+    if !is_after_space || spans.iter().any(|span| span.contains(&i)) {
+      continue;
+    }
+    let name = &line[i + 1..];
+    let len = name
+      .find(|c: char| !(c.is_alphanumeric() || matches!(c, '_' | '-' | '/')))
+      .unwrap_or(name.len());
+    if name[..len].chars().any(|c| !c.is_ascii_digit()) {
+      tags.push(i..i + 1 + len);
+    }
+  }
+  tags
+}
+
+/// `line` with its `#tags` left out or linked to the pages of the tags
+fn rewrite_tags(line: &str, rules: &ContentRules) -> String {
+  let mut text = String::with_capacity(line.len());
+  let mut curr = 0;
+  for range in tag_ranges(line) {
+    text.push_str(&line[curr..range.start]);
+    if rules.inline_tags == InlineTags::Link {
+      let tag = &line[range.clone()];
+      // Hugo keeps the slashes of nested tags like `#area/topic` in their urls,
+      // the leaf of split tags has a page
+      let name = match rules.nested_tags {
+        NestedTags::Keep => tag[1..].to_string(),
+        NestedTags::Leaf | NestedTags::Split => NestedTags::Leaf.apply(&tag[1..]).concat(),
+      };
+      let slugs: Vec<String> = name
+        .split('/')
+        .map(|part| rules.slug.to_url(part))
+        .collect();
+      let url = rules.slug.url(&format!("/tags/{}/", slugs.join("/")));
+      text.push_str(&format!("[{tag}]({url})"));
+    }
+    curr = range.end;
+  }
+  text.push_str(&line[curr..]);
+  text
+}
+
+/// Whether the `body` of a note has math outside of code
+fn has_math(body: &[String]) -> bool {
+  let mut fence: Option<&str> = None;
+  for line in body {
+    if let Some(open) = fence {
+      if closes_code_fence(open, line) {
+        fence = None;
+      }
+      continue;
+    }
+    fence = code_fence(line);
+    if fence.is_none()
+      && (math_fence(line).is_some() || !math_spans(line, &code_spans(line)).is_empty())
+    {
+      return true;
+    }
+  }
+  false
+}
+
+/// The run of 3 or more backticks or tildes opening or closing a fenced code block on `line`
+fn code_fence(line: &str) -> Option<&str> {
+  let trimmed = line.trim_start();
+  let c = trimmed.chars().next().filter(|c| *c == '`' || *c == '~')?;
+  let len = trimmed.chars().take_while(|other| *other == c).count();
+  (len >= 3).then(|| &trimmed[..len])
+}
+
+/// `line` with its md links to notes, like `[text](Some%20Note.md)`, `[text](./sub/Note.md#Heading)`
+/// or `[text](obsidian://open?vault=V&file=Some%20Note)`, written as wikilinks like `[[Some Note|text]]`
+/// to be resolved the same
+fn rewrite_md_links(line: &str) -> String {
+  let mut spans = code_spans(line);
+  spans.extend(math_spans(line, &spans));
+  let bytes = line.as_bytes();
+  let mut text = String::with_capacity(line.len());
+  let mut curr = 0;
+  let mut i = 0;
+  while i < bytes.len() {
+    let is_link_start = bytes[i] == b'['
+      && !matches!(
+        i.checked_sub(1).map(|prev| bytes[prev]),
+        Some(b'!' | b'\\' | b'[')
+      )
+      && bytes.get(i + 1) != Some(&b'[')
+      && !spans.iter().any(|span| span.contains(&i));
+    let is_autolink_start = bytes[i] == b'<' && !spans.iter().any(|span| span.contains(&i));
+    let link = if is_link_start {
+      md_note_link(&line[i..])
+    } else if is_autolink_start {
+      obsidian_autolink(&line[i..])
+    } else {
+      None
+    };
+    match link {
+      Some((len, wikilink)) => {
+        text.push_str(&line[curr..i]);
+        text.push_str(&wikilink);
+        i += len;
+        curr = i;
+      }
+      None => i += 1,
+    }
+  }
+  text.push_str(&line[curr..]);
+  text
+}
+
+/// `line` with the labels of its footnotes like `[^1]` prefixed with `prefix` and without spaces,
+/// and its inline footnotes like `^[Some note]` written as references to footnotes,
+/// whose definitions are added to `footnotes` after a blank line
+fn rewrite_footnotes(line: &str, prefix: &str, footnotes: &mut Vec<String>) -> String {
+  let mut spans = code_spans(line);
+  spans.extend(math_spans(line, &spans));
+  let bytes = line.as_bytes();
+  let mut text = String::with_capacity(line.len());
+  let mut curr = 0;
+  let mut i = 0;
+  while i + 1 < bytes.len() {
+    let is_text = !spans.iter().any(|span| span.contains(&i));
+    let is_escaped = i > 0 && bytes[i - 1] == b'\\';
+    if !is_text || is_escaped {
+      i += 1;
+      continue;
+    }
+    if bytes[i..].starts_with(b"[^") {
+      let end = line[i + 2..].find(']').map(|end| i + 2 + end);
+      let label = end
+        .map(|end| &line[i + 2..end])
+        .filter(|label| !label.trim().is_empty() && !label.contains(['[', '^']));
+      if let (Some(end), Some(label)) = (end, label) {
+        let label = label.split_whitespace().collect::<Vec<_>>().join("-");
+        text.push_str(&line[curr..i]);
+        text.push_str(&format!("[^{prefix}{label}]"));
+        i = end + 1;
+        curr = i;
+        continue;
+      }
+    } else if bytes[i..].starts_with(b"^[") && (i == 0 || bytes[i - 1] != b'[') {
+      // The note may hold brackets, like `^[See [[Other Note]]]`
+      let mut depth = 0;
+      let end = line[i + 1..].char_indices().find_map(|(j, c)| {
+        match c {
+          '[' => depth += 1,
+          ']' => depth -= 1,
+          _ => {}
+        }
+        (depth == 0).then_some(i + 1 + j)
+      });
+      if let Some(end) = end {
+        if footnotes.is_empty() {
+          footnotes.push(String::new());
+        }
+        let label = format!("{prefix}inline-{}", footnotes.len());
+        footnotes.push(format!("[^{label}]: {}", &line[i + 2..end]));
+        text.push_str(&line[curr..i]);
+        text.push_str(&format!("[^{label}]"));
+        i = end + 1;
+        curr = i;
+        continue;
+      }
+    }
+    i += 1;
+  }
+  text.push_str(&line[curr..]);
+  text
+}
+
+/// `line` without its inline Dataview queries, code spans like `` `= this.rating` `` or `` `$= dv.pages().length` ``
+fn strip_inline_queries(line: &str) -> String {
+  let mut text = String::with_capacity(line.len());
+  let mut curr = 0;
+  for span in code_spans(line) {
+    let code = line[span.clone()].trim_matches('`').trim_start();
+    let query = code.strip_prefix("$=").or_else(|| code.strip_prefix('='));
+    if query.is_some_and(|query| query.starts_with(char::is_whitespace)) {
+      text.push_str(&line[curr..span.start]);
+      curr = span.end;
+    }
+  }
+  text.push_str(&line[curr..]);
+  text
+}
+
+/// `line` with its `==highlights==` outside of code and math written with `highlight`,
+/// `{text}` standing for the highlighted text
+fn rewrite_highlights(line: &str, highlight: &str) -> String {
+  let mut spans = code_spans(line);
+  spans.extend(math_spans(line, &spans));
+  let is_text = |i: usize| !spans.iter().any(|span| span.contains(&i));
+  let bytes = line.as_bytes();
+  // Like emphasis, `==` opens before a non-space and closes after one, so `a == b` stays text
+  let is_mark = |i: usize| bytes[i..].starts_with(b"==") && is_text(i);
+  let mut text = String::with_capacity(line.len());
+  let mut curr = 0;
+  let mut i = 0;
+  while i + 2 <= bytes.len() {
+    let opens = is_mark(i)
+      && bytes
+        .get(i + 2)
+        .is_some_and(|next| !next.is_ascii_whitespace() && *next != b'=')
+      && (i == 0 || bytes[i - 1] != b'=');
+    let close = opens
+      .then(|| {
+        (i + 3..bytes.len()).find(|&end| {
+          is_mark(end)
+            && !bytes[end - 1].is_ascii_whitespace()
+            && bytes[end - 1] != b'='
+            && bytes.get(end + 2) != Some(&b'=')
+        })
+      })
+      .flatten();
+    match close {
+      Some(end) => {
+        text.push_str(&line[curr..i]);
+        text.push_str(&highlight.replace("{text}", &line[i + 2..end]));
+        i = end + 2;
+        curr = i;
+      }
+      None => i += 1,
+    }
+  }
+  text.push_str(&line[curr..]);
+  text
+}
+
+/// The length of the md link to a note starting `text`, and the link as a wikilink
+fn md_note_link(text: &str) -> Option<(usize, String)> {
+  // The label may hold brackets, like `[a [b] c](Note.md)`
+  let mut depth = 0;
+  let label_end = text.char_indices().find_map(|(i, c)| {
+    match c {
+      '[' => depth += 1,
+      ']' => depth -= 1,
+      _ => {}
+    }
+    (depth == 0).then_some(i)
+  })?;
+  let label = &text[1..label_end];
+  let rest = text[label_end + 1..].strip_prefix('(')?;
+  // `<Some Note.md>` may hold spaces, a title may follow the target
+  let (target, rest) = match rest.strip_prefix('<') {
+    Some(rest) => rest.split_once('>')?,
+    None => rest.split_at(rest.find(|c: char| c == ')' || c.is_whitespace())?),
+  };
+  let link_end = rest.find(')')?;
+  let len = text.len() - rest.len() + link_end + 1;
+  if label.contains("]]") || label.contains("[[") {
+    return None;
+  }
+  let uri = target.strip_prefix(OBSIDIAN_URI);
+  let target = match uri {
+    Some(uri) => match obsidian_uri_file(uri) {
+      Some(file) => file,
+      // Only the app opens the uri, its text is kept
+      None => return Some((len, label.to_string())),
+    },
+    None => percent_decode(target),
+  };
+  let (path, anchor) = match target.split_once('#') {
+    Some((path, anchor)) => (path, Some(anchor)),
+    None => (target.as_str(), None),
+  };
+  let name = match uri {
+    // The uris of Obsidian name notes without their extension
+    Some(_) => path.strip_suffix(".md").unwrap_or(path),
+    None if path.contains("://") => return None,
+    None => path.strip_suffix(".md")?,
+  };
+  // Relative to the note or to the vault, the folders the link leaves are dropped
+  let name = name.trim_start_matches(['.', '/']);
+  let wikilink = match (anchor, label.trim()) {
+    (Some(anchor), "") => format!("[[{name}#{anchor}]]"),
+    (Some(anchor), label) => format!("[[{name}#{anchor}|{label}]]"),
+    (None, "") => format!("[[{name}]]"),
+    (None, label) => format!("[[{name}|{label}]]"),
+  };
+  Some((len, wikilink))
+}
+
+/// The length of the autolink like `<obsidian://open?vault=V&file=Note>` starting `text`,
+/// and the link as a wikilink
+fn obsidian_autolink(text: &str) -> Option<(usize, String)> {
+  let (uri, _) = text
+    .strip_prefix('<')?
+    .strip_prefix(OBSIDIAN_URI)?
+    .split_once('>')?;
+  let file = obsidian_uri_file(uri)?;
+  let name = match file.split_once('#') {
+    Some((path, anchor)) => format!("{}#{anchor}", path.strip_suffix(".md").unwrap_or(path)),
+    None => file.strip_suffix(".md").unwrap_or(&file).to_string(),
+  };
+  Some((OBSIDIAN_URI.len() + uri.len() + 2, format!("[[{name}]]")))
+}
+
+/// The note an Obsidian uri like `open?vault=V&file=Some%20Note` opens, decoded
+fn obsidian_uri_file(uri: &str) -> Option<String> {
+  let query = uri.strip_prefix("open?")?;
+  query
+    .split('&')
+    .find_map(|param| param.strip_prefix("file="))
+    .map(percent_decode)
+}
+
+/// `text` with its `%20` like escapes decoded
+fn percent_decode(text: &str) -> String {
+  let bytes = text.as_bytes();
+  let mut decoded = Vec::with_capacity(bytes.len());
+  let mut i = 0;
+  while i < bytes.len() {
+    let byte = (bytes[i] == b'%')
+      .then(|| text.get(i + 1..i + 3))
+      .flatten()
+      .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+    match byte {
+      Some(byte) => {
+        decoded.push(byte);
+        i += 3;
+      }
+      None => {
+        decoded.push(bytes[i]);
+        i += 1;
+      }
+    }
+  }
+  String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// The `$$` opening a math block on `line`, unless the block ends on the line too
+fn math_fence(line: &str) -> Option<&'static str> {
+  let rest = line.trim().strip_prefix(MATH_FENCE)?;
+  (!rest.contains(MATH_FENCE)).then_some(MATH_FENCE)
+}
+
+/// `text` with the dollars of its math outside of code written as `\\(inline\\)` and `\\[display\\]`
+fn bracket_math(text: &str) -> String {
+  let code = code_spans(text);
+  let mut bracketed = String::with_capacity(text.len());
+  let mut curr = 0;
+  for span in math_spans(text, &code) {
+    let math = &text[span.clone()];
+    bracketed.push_str(&text[curr..span.start]);
+    match math
+      .strip_prefix(MATH_FENCE)
+      .and_then(|math| math.strip_suffix(MATH_FENCE))
+    {
+      Some(math) => bracketed.push_str(&format!("\\[{math}\\]")),
+      None => bracketed.push_str(&format!("\\({}\\)", &math[1..math.len() - 1])),
+    }
+    curr = span.end;
+  }
+  bracketed.push_str(&text[curr..]);
+  bracketed
+}
+
+/// `line` without its `%%comments%%` outside of code spans. `is_comment` tells whether
+/// a comment opened on a line above goes on, and is set for the lines below.
+fn strip_comments(line: &str, is_comment: &mut bool) -> String {
+  let spans = code_spans(line);
+  let mut text = String::with_capacity(line.len());
+  let mut curr = 0;
+  let mut i = 0;
+  while let Some(pos) = line[i..].find("%%") {
+    let at = i + pos;
+    if let Some(span) = spans.iter().find(|span| !*is_comment && span.contains(&at)) {
+      i = span.end;
+      continue;
+    }
+    if *is_comment {
+      curr = at + 2;
+    } else {
+      text.push_str(&line[curr..at]);
+    }
+    *is_comment = !*is_comment;
+    i = at + 2;
+  }
+  if !*is_comment {
+    text.push_str(&line[curr..]);
+  }
+  text
+}
+
+/// HTML elements running code or loading other pages, left out with their content
+const UNSAFE_ELEMENTS: [&str; 8] = [
+  "script", "style", "iframe", "frame", "frameset", "object", "embed", "applet",
+];
+
+/// HTML elements without content, which have no end tag
+const VOID_ELEMENTS: [&str; 2] = ["embed", "frame"];
+
+/// An HTML tag or comment of a line
+struct HtmlTag<'a> {
+  /// Byte range of the tag in the line
+  range: Range<usize>,
+  /// Lowercase name of the element, empty for comments
+  name: String,
+  /// The attributes of the tag, between its name and `>`
+  attrs: &'a str,
+  is_end: bool,
+}
+
+/// The HTML tags and `<!-- comments -->` of `line` outside of code spans. Autolinks like
+/// `<https://example.com>` are not tags, their scheme being followed by `:`.
+fn html_tags(line: &str) -> Vec<HtmlTag<'_>> {
+  let spans = code_spans(line);
+  let mut tags = vec![];
+  let mut i = 0;
+  while let Some(pos) = line[i..].find('<') {
+    let at = i + pos;
+    i = at + 1;
+    if let Some(span) = spans.iter().find(|span| span.contains(&at)) {
+      i = span.end;
+      continue;
+    }
+    let rest = &line[at + 1..];
+    if rest.starts_with("!--") {
+      if let Some(end) = rest.find("-->") {
+        i = at + 1 + end + 3;
+        let (range, name, attrs, is_end) = (at..i, String::new(), "", false);
+        tags.push(HtmlTag {
+          range,
+          name,
+          attrs,
+          is_end,
+        });
+      }
+      continue;
+    }
+    let (is_end, rest) = match rest.strip_prefix('/') {
+      Some(rest) => (true, rest),
+      None => (false, rest),
+    };
+    let name_len = rest
+      .find(|c: char| !c.is_ascii_alphanumeric() && c != '-')
+      .unwrap_or(rest.len());
+    let (name, after) = rest.split_at(name_len);
+    if !name.starts_with(|c: char| c.is_ascii_alphabetic())
+      || !after.starts_with(|c: char| c.is_ascii_whitespace() || c == '/' || c == '>')
+    {
+      continue;
+    }
+    // `>` ends the tag outside of quoted attribute values
+    let mut quote = None;
+    let Some(end) = after.char_indices().find_map(|(j, c)| {
+      match (quote, c) {
+        (None, '"' | '\'') => quote = Some(c),
+        (Some(open), _) if open == c => quote = None,
+        (None, '>') => return Some(j),
+        _ => (),
+      }
+      None
+    }) else {
+      continue;
+    };
+    let start = line.len() - after.len();
+    i = start + end + 1;
+    let name = name.to_ascii_lowercase();
+    let attrs = &line[start..start + end];
+    tags.push(HtmlTag {
+      range: at..i,
+      name,
+      attrs,
+      is_end,
+    });
+  }
+  tags
+}
+
+/// `line` with its HTML stripped or sanitized as `policy` says. `unsafe_element` names the
+/// unsafe element opened on a line above, whose content is left out, and is set for the lines below.
+fn rewrite_html(line: &str, policy: RawHtml, unsafe_element: &mut Option<String>) -> String {
+  let mut text = String::with_capacity(line.len());
+  let mut curr = 0;
+  let mut tags = html_tags(line).into_iter();
+  loop {
+    if let Some(name) = unsafe_element {
+      // Backticks are no code spans in scripts, so the end tag is looked for in the rest of the line
+      let Some(pos) = line[curr..].to_ascii_lowercase().find(&format!("</{name}")) else {
+        return text;
+      };
+      let at = curr + pos;
+      curr = line[at..].find('>').map_or(line.len(), |end| at + end + 1);
+      *unsafe_element = None;
+    }
+    let Some(tag) = tags.next() else {
+      break;
+    };
+    if tag.range.start < curr {
+      continue;
+    }
+    text.push_str(&line[curr..tag.range.start]);
+    curr = tag.range.end;
+    match policy {
+      RawHtml::Keep => text.push_str(&line[tag.range]),
+      _ if UNSAFE_ELEMENTS.contains(&tag.name.as_str()) => {
+        let is_void = VOID_ELEMENTS.contains(&tag.name.as_str()) || tag.attrs.ends_with('/');
+        if !tag.is_end && !is_void {
+          *unsafe_element = Some(tag.name);
+        }
+      }
+      RawHtml::Strip => (),
+      RawHtml::Sanitize if tag.name.is_empty() || tag.is_end => text.push_str(&line[tag.range]),
+      RawHtml::Sanitize => {
+        text.push_str(&line[tag.range.start..tag.range.end - tag.attrs.len() - 1]);
+        text.push_str(&safe_attrs(tag.attrs));
+        text.push('>');
+      }
+    }
+  }
+  text.push_str(&line[curr..]);
+  text
+}
+
+/// The HTML attributes `attrs` without event handlers like `onclick` and `javascript:` urls
+fn safe_attrs(attrs: &str) -> String {
+  let mut safe = String::with_capacity(attrs.len());
+  let mut rest = attrs;
+  loop {
+    let attr = rest.trim_start();
+    if attr.is_empty() {
+      break;
+    }
+    let name_len = attr
+      .find(|c: char| c.is_ascii_whitespace() || c == '=')
+      .unwrap_or(attr.len())
+      .max(1);
+    let (name, mut after) = attr.split_at(name_len);
+    let mut value = "";
+    if let Some(val) = after.trim_start().strip_prefix('=') {
+      let val = val.trim_start();
+      let len = match val.chars().next() {
+        Some(quote @ ('"' | '\'')) => val[1..].find(quote).map_or(val.len(), |end| end + 2),
+        _ => val
+          .find(|c: char| c.is_ascii_whitespace())
+          .unwrap_or(val.len()),
+      };
+      (value, after) = val.split_at(len);
+    }
+    let url: String = value
+      .chars()
+      .filter(|c| !c.is_ascii_whitespace() && !matches!(c, '"' | '\''))
+      .collect();
+    let is_handler = name.len() > 2 && name.get(..2).is_some_and(|p| p.eq_ignore_ascii_case("on"));
+    if !is_handler && !url.to_ascii_lowercase().starts_with("javascript:") {
+      safe.push(' ');
+      safe.push_str(&attr[..attr.len() - after.len()]);
+    }
+    rest = after;
+  }
+  safe
+}
+
+/// The byte ranges of the `$inline$` and `$$display$$` math of `line` outside of `code`,
+/// dollars included. Like in Pandoc, `$` opens math before a non-space and closes it after one,
+/// unless a digit follows, so prices stay text.
+fn math_spans(line: &str, code: &[Range<usize>]) -> Vec<Range<usize>> {
+  let bytes = line.as_bytes();
+  let is_text = |i: usize| !code.iter().any(|span| span.contains(&i));
+  let is_dollar = |i: usize| bytes[i] == b'$' && (i == 0 || bytes[i - 1] != b'\\') && is_text(i);
+  let mut spans = vec![];
+  let mut i = 0;
+  while i < bytes.len() {
+    if !is_dollar(i) {
+      i += 1;
+      continue;
+    }
+    let end = if line[i..].starts_with(MATH_FENCE) {
+      line[i + 2..]
+        .find(MATH_FENCE)
+        .map(|pos| i + 2 + pos + 2)
+        .filter(|end| is_text(end - 1))
+    } else if bytes
+      .get(i + 1)
+      .is_some_and(|next| !next.is_ascii_whitespace())
+    {
+      (i + 2..bytes.len())
+        .find(|j| {
+          is_dollar(*j)
+            && !bytes[j - 1].is_ascii_whitespace()
+            && !bytes.get(j + 1).is_some_and(u8::is_ascii_digit)
+        })
+        .map(|j| j + 1)
+    } else {
+      None
+    };
+    match end {
+      Some(end) => {
+        spans.push(i..end);
+        i = end;
+      }
+      None => i += 1,
+    }
+  }
+  spans
+}
+
+/// The byte ranges of the `code spans` of `line`, backticks included.
+/// A span ends with a run of as many backticks as it starts with.
+fn code_spans(line: &str) -> Vec<Range<usize>> {
+  let bytes = line.as_bytes();
+  let run_end = |start: usize| {
+    (start..bytes.len())
+      .find(|i| bytes[*i] != b'`')
+      .unwrap_or(bytes.len())
+  };
+  let mut spans = vec![];
+  let mut i = 0;
+  while i < bytes.len() {
+    if bytes[i] != b'`' {
+      i += 1;
+      continue;
+    }
+    let start = i;
+    i = run_end(start);
+    let len = i - start;
+    let mut j = i;
+    while j < bytes.len() {
+      if bytes[j] != b'`' {
+        j += 1;
+        continue;
+      }
+      let close = j;
+      j = run_end(close);
+      if j - close == len {
+        spans.push(start..j);
+        i = j;
+        break;
+      }
+    }
+  }
+  spans
+}
+
+/// Whether `line` is the `| --- | :-: |` row between the header and the rows of a table
+fn is_table_delimiter(line: &str) -> bool {
+  let line = line.trim();
+  line.contains('|')
+    && line.contains('-')
+    && line
+      .chars()
+      .all(|c| matches!(c, '|' | '-' | ':' | ' ' | '\t'))
+}
+
+/// Whether `line` is an item of a bullet or numbered list
+fn is_list_item(line: &str) -> bool {
+  let trimmed = line.trim_start();
+  let marker = trimmed.trim_start_matches(|c: char| c.is_ascii_digit());
+  let marker = if marker.len() < trimmed.len() {
+    marker.strip_prefix(['.', ')'])
+  } else {
+    marker.strip_prefix(['-', '*', '+'])
+  };
+  marker.is_some_and(|rest| rest.is_empty() || rest.starts_with(' '))
+}
+
+/// The text of a blockquote line after its `>` and the space following it
+fn strip_quote(line: &str) -> Option<&str> {
+  let text = line.trim_start().strip_prefix('>')?;
+  Some(text.strip_prefix(' ').unwrap_or(text))
+}
+
+/// The first line of an Obsidian callout, like `> [!tip]- Some title`
+struct Callout<'a> {
+  /// The `>` quoting the line, and the spaces around them
+  quote: &'a str,
+  /// Type of the callout, in lower case
+  kind: String,
+  /// Whether `-` folds the callout
+  folded: bool,
+  title: Option<&'a str>,
+}
+
+impl Callout<'_> {
+  /// The title of the callout, its type like `Tip` by default
+  fn title(&self) -> String {
+    match self.title {
+      Some(title) => title.to_string(),
+      None => {
+        let mut chars = self.kind.chars();
+        chars
+          .next()
+          .map(|first| first.to_uppercase().chain(chars).collect())
+          .unwrap_or_default()
+      }
+    }
+  }
+}
+
+/// The callout `line` opens, if any
+fn parse_callout(line: &str) -> Option<Callout<'_>> {
+  let text = line.trim_start_matches(|c: char| c == '>' || c.is_whitespace());
+  let quote = &line[..line.len() - text.len()];
+  if !quote.contains('>') {
+    return None;
+  }
+  let (kind, rest) = text.strip_prefix("[!")?.split_once(']')?;
+  if kind.is_empty() || kind.contains(char::is_whitespace) {
+    return None;
+  }
+  let (folded, rest) = match rest.strip_prefix(['-', '+']) {
+    Some(title) => (rest.starts_with('-'), title),
+    None => (false, rest),
+  };
+  let title = rest.trim();
+  Some(Callout {
+    quote,
+    kind: kind.to_lowercase(),
+    folded,
+    title: (!title.is_empty()).then_some(title),
+  })
+}
+
+/// Write the opening of the shortcode `name` of a callout, like `{{< admonition tip "Title" false >}}`,
+/// `false` closing folded callouts
+fn write_callout_open(writer: &mut dyn Write, callout: &Callout, name: &str) -> Result<()> {
+  let kind = CALLOUT_ALIASES
+    .iter()
+    .find(|(alias, _)| *alias == callout.kind)
+    .map_or(callout.kind.as_str(), |(_, kind)| kind);
+  write!(writer, "{{{{< {name} {kind}")?;
+  if callout.title.is_some() || callout.folded {
+    write!(writer, " \"{}\"", callout.title().replace('"', "\\\""))?;
+  }
+  if callout.folded {
+    write!(writer, " false")?;
+  }
+  writeln!(writer, " >}}}}")?;
+  Ok(())
+}
+
+/// The byte range of the checkbox of a task like `- [ ] Todo` and its status, ` ` or `x` in Obsidian
+/// and others like `/` with some themes
+fn task_checkbox(line: &str) -> Option<(Range<usize>, char)> {
+  if !is_list_item(line) {
+    return None;
+  }
+  let trimmed = line.trim_start();
+  let marker_len = trimmed.find(' ')?;
+  let item = &trimmed[marker_len..];
+  let rest = item.trim_start().strip_prefix('[')?;
+  let mut chars = rest.chars();
+  let status = chars.next()?;
+  let after = chars.as_str().strip_prefix(']')?;
+  if !(after.is_empty() || after.starts_with(' ')) {
+    return None;
+  }
+  let start = line.len() - rest.len() - 1;
+  Some((start..line.len() - after.len(), status))
+}
+
+/// The level of a `## Heading` line
+fn heading_level(line: &str) -> Option<usize> {
+  let level = line.chars().take_while(|c| *c == '#').count();
+  (level > 0 && line[level..].starts_with(' ')).then_some(level)
+}
+
+/// What `line` becomes if it only holds the url of a video, bare or embedded like `![](url)`,
+/// from the first of `videos` matching it. The scheme and `www.` of urls and patterns are ignored,
+/// `{id}` matches letters, digits, `-` and `_`, and query params may follow the pattern.
+fn video_embed(line: &str, videos: &BTreeMap<String, String>) -> Option<String> {
+  let url = line
+    .strip_prefix("![")
+    .and_then(|rest| rest.split_once("]("))
+    .and_then(|(_, url)| url.strip_suffix(')'))
+    .unwrap_or(line);
+  fn bare(url: &str) -> &str {
+    let url = url.split_once("://").map_or(url, |(_, rest)| rest);
+    url.strip_prefix("www.").unwrap_or(url)
+  }
+  if !url.starts_with("https://") && !url.starts_with("http://") {
+    return None;
+  }
+  let url = bare(url);
+  videos.iter().find_map(|(pattern, embed)| {
+    let pattern = bare(pattern);
+    let (prefix, suffix) = pattern.split_once("{id}")?;
+    let rest = url.strip_prefix(prefix)?;
+    let id_len = rest
+      .find(|c: char| !c.is_ascii_alphanumeric() && c != '-' && c != '_')
+      .unwrap_or(rest.len());
+    let (id, rest) = rest.split_at(id_len);
+    let rest = rest.strip_prefix(suffix)?;
+    let is_end = rest.is_empty() || rest.starts_with(['?', '&', '#']);
+    (!id.is_empty() && is_end).then(|| embed.replace("{id}", id))
+  })
+}
+
+/// The width of the indentation of `line`, a tab counting as 4 spaces
+fn indent_width(line: &str) -> usize {
+  line
+    .chars()
+    .take_while(|c| c.is_whitespace())
+    .map(|c| if c == '\t' { 4 } else { 1 })
+    .sum()
+}
+
+/// Whether `[[inner]]` links to an image rather than a note
+fn is_asset(inner: &str) -> bool {
+  let target = inner.split('|').next().unwrap_or(inner).trim();
+  let ext = target.rsplit_once('.').map(|(_, ext)| ext.to_lowercase());
+  ext.is_some_and(|ext| IMAGE_EXTENSIONS.contains(&ext.as_str()))
+}
+
+/// The width and height of `400x300`, or the width of `400`
+fn parse_size(size: &str) -> Option<(u32, Option<u32>)> {
+  match size.split_once('x') {
+    Some((width, height)) => Some((
+      width.trim().parse().ok()?,
+      Some(height.trim().parse().ok()?),
+    )),
+    None => Some((size.parse().ok()?, None)),
+  }
+}
+
+/// The line without the `^id` ending a block, and the id
+fn split_block_id(line: &str) -> (&str, Option<&str>) {
+  let trimmed = line.trim_end();
+  match trimmed.rsplit_once('^') {
+    Some((rest, id))
+      if !id.is_empty()
+        && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        && (rest.is_empty() || rest.ends_with(char::is_whitespace)) =>
+    {
+      (rest.trim_end(), Some(id))
+    }
+    _ => (line, None),
+  }
+}
+
+/// The id of the anchor of a block, `block-abc123` for `^abc123`
+fn block_anchor(id: &str) -> String {
+  format!("block-{id}")
+}
+
+/// Write the `[[inner]]` link as a md link, `[[Note|Label]]` showing `Label`
+/// and `[[Note#Heading]]` or `[[Note#^block]]` linking to the heading or block
+fn write_wikilink(
+  writer: &mut dyn Write,
+  inner: &str,
+  embed: bool,
+  rules: &ContentRules,
+  on_asset: &mut dyn FnMut(&str, &str) -> Result<()>,
+  on_link: &mut dyn FnMut(&str) -> Result<Option<String>>,
+) -> Result<()> {
+  let (target, label) = match inner.split_once('|') {
+    Some((target, label)) => (target.trim(), Some(label.trim()).filter(|l| !l.is_empty())),
+    None => (inner.trim(), None),
+  };
+  if target.is_empty() {
+    write!(writer, "[[{inner}]]")?;
+  } else if is_asset(target) {
+    let url = rules.slug.to_url(target);
+    on_asset(target, &url)?;
+    let src = rules.slug.asset_url(&url);
+    // `![[img.png|400]]` and `![[img.png|400x300]]` set the size of the image,
+    // `![[img.png|A sunset]]` its alt text, and both `![[img.png|A sunset|400]]`
+    let parts = label.map_or(vec![], |label| label.split('|').map(str::trim).collect());
+    let size = parts.iter().find_map(|part| parse_size(part));
+    let label = parts
+      .iter()
+      .find(|part| !part.is_empty() && parse_size(part).is_none())
+      .copied();
+    let caption = label.filter(|_| rules.figure_caption);
+    let figure = caption.is_some()
+      || match rules.figure_shortcode {
+        FigureShortcode::Never => false,
+        FigureShortcode::Sized => size.is_some(),
+        FigureShortcode::Always => true,
+      };
+    if !embed {
+      write!(writer, "[{}]({src})", label.unwrap_or(&url))?;
+    } else if figure {
+      write!(writer, "{{{{< figure src=\"{src}\"")?;
+      if let Some(label) = label {
+        write!(writer, " alt=\"{}\"", label.replace('"', "\\\""))?;
+      }
+      if let Some(caption) = caption {
+        write!(writer, " caption=\"{}\"", caption.replace('"', "\\\""))?;
+      }
+      if let Some((width, height)) = size {
+        write!(writer, " width=\"{width}\"")?;
+        if let Some(height) = height {
+          write!(writer, " height=\"{height}\"")?;
+        }
+      }
+      write!(writer, " >}}}}")?;
+    } else {
+      let alt = label.map_or(url.clone(), |label| {
+        label.replace('[', "\\[").replace(']', "\\]")
+      });
+      write!(writer, "![{alt}]({src})")?;
+    }
+  } else {
+    // `[[Note#Heading#Sub heading]]` links to the last heading
+    let (note, heading) = match target.split_once('#') {
+      Some((note, headings)) => (note.trim(), headings.rsplit('#').next()),
+      None => (target, None),
+    };
+    let heading = heading.map(str::trim).filter(|heading| !heading.is_empty());
+    let anchor = heading.map(|heading| match heading.strip_prefix('^') {
+      Some(id) => block_anchor(id),
+      None => heading_anchor(heading),
+    });
+    let url = if note.is_empty() {
+      Some(String::new())
+    } else {
+      on_link(note)?
+    };
+    // Block ids mean nothing to readers, `[[Note#^id]]` shows `Note`
+    let text = match (label, heading) {
+      (Some(label), _) => label.to_string(),
+      (None, Some(heading)) if note.is_empty() => heading.trim_start_matches('^').to_string(),
+      (None, Some(heading)) if heading.starts_with('^') => note.to_string(),
+      (None, Some(heading)) => format!("{note} – {heading}"),
+      (None, None) => note.to_string(),
+    };
+    match (url, anchor) {
+      (Some(url), Some(anchor)) => write!(writer, "[{text}]({url}#{anchor})")?,
+      (Some(url), None) => write!(writer, "[{text}]({url})")?,
+      // The post of an unpublished note would be a dead link
+      (None, _) => write!(
+        writer,
+        "{}",
+        rules.unpublished_link.replace("{text}", &text)
+      )?,
+    }
+  }
+  Ok(())
+}
+
+/// Url of the post of the note `name` links point to when it is not found in the vault
+pub fn default_link_url(name: &str, rules: &ContentRules) -> String {
+  let slug = &rules.slug;
+  slug.url(&format!("{}{}/", slug.posts_url, slug.to_url(name)))
+}
+
+/// The id Hugo gives a heading, like `some-heading` for `Some Heading!`
+fn heading_anchor(heading: &str) -> String {
+  heading
+    .trim()
+    .to_lowercase()
+    .chars()
+    .filter_map(|c| match c {
+      ' ' => Some('-'),
+      c if c.is_alphanumeric() || c == '-' || c == '_' => Some(c),
+      _ => None,
+    })
+    .collect()
+}
+
+// fn to_hex_path(path: &Path) -> String {
+//   let bytes = path.as_os_str().as_encoded_bytes();
+//   let mut p = bytes[..bytes.len() - 3]
+//     .iter()
+//     .map(|b| format!("{:02x}", b))
+//     .collect::<Vec<String>>()
+//     .join("");
+//   p.push_str(".md");
+//   p
+// }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// The content of `note` as written with the config `config`
+  fn convert(config: &str, note: &str) -> String {
+    convert_embedding(config, note, &[])
+  }
+
+  /// The content of `note` embedding the notes of `embeds`, by name
+  fn convert_embedding(config: &str, note: &str, embeds: &[(&str, &str)]) -> String {
+    let rules = ContentRules::new(&Config::from_toml(config));
+    let mut out = vec![];
+    write_content(
+      &mut out,
+      note.lines().map(str::to_string),
+      &rules,
+      &mut |_, _| Ok(()),
+      &mut |name| Ok(Some(default_link_url(name, &rules))),
+      &mut |name| {
+        let embed = embeds.iter().find(|(embed, _)| *embed == name);
+        Ok(embed.map(|(_, note)| note.lines().map(str::to_string).collect()))
+      },
+    )
+    .unwrap();
+    String::from_utf8(out).unwrap()
+  }
+
+  #[test]
+  fn md_links_to_notes_become_wikilinks() {
+    assert_eq!(
+      rewrite_md_links("[text](Some%20Note.md) and [t](./sub/Note.md#Heading)"),
+      "[[Some Note|text]] and [[sub/Note#Heading|t]]"
+    );
+    assert_eq!(rewrite_md_links("[a [b] c](Note.md)"), "[[Note|a [b] c]]");
+    assert_eq!(
+      rewrite_md_links("[t](<Some Note.md> \"Title\")"),
+      "[[Some Note|t]]"
+    );
+    assert_eq!(rewrite_md_links("[](../Note.md)"), "[[Note]]");
+  }
+
+  #[test]
+  fn md_links_to_other_targets_are_kept() {
+    for line in [
+      "[t](https://example.com/a.md)",
+      "[t](photo.png)",
+      "[t](Note.md",
+      "`[t](Note.md)`",
+      "$[t](Note.md)$",
+      "[[Note]] and [t]",
+    ] {
+      assert_eq!(rewrite_md_links(line), line);
+    }
+  }
+
+  #[test]
+  fn md_links_in_code_blocks_are_kept() {
+    let note = "[t](Note.md)\n\n    [t](Note.md)\n\n```\n[t](Note.md)\n```";
+    assert_eq!(
+      convert("", note),
+      "[t](/posts/note/)\n\n    [t](Note.md)\n\n```\n[t](Note.md)\n```\n"
+    );
+  }
+
+  #[test]
+  fn strip_comments_leaves_code_spans() {
+    let mut is_comment = false;
+    assert_eq!(strip_comments("a %%b%% c", &mut is_comment), "a  c");
+    assert_eq!(
+      strip_comments("`%%x%%` y %%z%%", &mut is_comment),
+      "`%%x%%` y "
+    );
+    assert_eq!(
+      strip_comments("``a `%%` b`` c", &mut is_comment),
+      "``a `%%` b`` c"
+    );
+    assert!(!is_comment);
+  }
+
+  #[test]
+  fn strip_comments_goes_on_over_lines() {
+    let mut is_comment = false;
+    assert_eq!(strip_comments("a %%b", &mut is_comment), "a ");
+    assert!(is_comment);
+    assert_eq!(strip_comments("`c`", &mut is_comment), "");
+    // Backticks in a comment open no code span hiding its end
+    assert_eq!(strip_comments("`d%% e` %%f%%", &mut is_comment), " e` ");
+    assert!(!is_comment);
+    assert_eq!(strip_comments("%%", &mut is_comment), "");
+    assert!(is_comment);
+  }
+
+  #[test]
+  fn comments_are_left_out_of_posts() {
+    let note = "a %%b%%\n%%\n[[Hidden]]\n\n%%\nc\n\n    %%code%%\n\n```\n%%x%%\n```";
+    assert_eq!(
+      convert("", note),
+      "a \nc\n\n    %%code%%\n\n```\n%%x%%\n```\n"
+    );
+  }
+
+  #[test]
+  fn footnote_labels_lose_their_spaces() {
+    let mut footnotes = vec![];
+    assert_eq!(
+      rewrite_footnotes(
+        "a[^1] b[^my note] `[^2]` \\[^3] [^] [^[x]]",
+        "",
+        &mut footnotes
+      ),
+      "a[^1] b[^my-note] `[^2]` \\[^3] [^] [^[x]]"
+    );
+    assert_eq!(
+      rewrite_footnotes("[^1]: Def", "2-", &mut footnotes),
+      "[^2-1]: Def"
+    );
+    assert!(footnotes.is_empty());
+  }
+
+  #[test]
+  fn inline_footnotes_become_definitions() {
+    let mut footnotes = vec![];
+    assert_eq!(
+      rewrite_footnotes("a^[One] b^[See [[Note]]] `^[c]` [^[d]]", "", &mut footnotes),
+      "a[^inline-1] b[^inline-2] `^[c]` [^[d]]"
+    );
+    assert_eq!(
+      footnotes,
+      ["", "[^inline-1]: One", "[^inline-2]: See [[Note]]"]
+    );
+  }
+
+  #[test]
+  fn footnotes_of_embedded_notes_get_unique_labels() {
+    let note =
+      "A[^1] and ^[inline].\n\n![[Other]]\n\n    [^1] code\n\n```\n^[x]\n```\n[^1]: Of the note";
+    let other = "B[^1] and ^[other inline].\n\n[^1]: Of [[Other]]";
+    assert_eq!(
+      convert_embedding("", note, &[("Other", other)]),
+      "A[^1] and [^inline-1].\n\n\n\
+      B[^1-1] and [^1-inline-1].\n\n[^1-1]: Of [Other](/posts/other/)\n\
+      \n[^1-inline-1]: other inline\n\n\n    [^1] code\n\n```\n^[x]\n```\n[^1]: Of the note\n\
+      \n[^inline-1]: inline\n"
+    );
+  }
+
+  #[test]
+  fn safe_attrs_handles_non_ascii_names() {
+    assert_eq!(safe_attrs(" 日本=1 onclick=\"y()\""), " 日本=1");
+    assert_eq!(safe_attrs(" é"), " é");
+    assert_eq!(
+      rewrite_html("<a 日本=1>x</a>", RawHtml::Sanitize, &mut None),
+      "<a 日本=1>x</a>"
+    );
+  }
+
+  #[test]
+  fn safe_attrs_drops_handlers_and_script_urls() {
+    assert_eq!(
+      safe_attrs(" href=\" JavaScript:alert(1)\" title='a > b' ONCLICK=x"),
+      " title='a > b'"
+    );
+    assert_eq!(safe_attrs(" class=\"x\" /"), " class=\"x\" /");
+  }
+
+  #[test]
+  fn rewrite_html_strips_or_sanitizes() {
+    let mut open = None;
+    assert_eq!(
+      rewrite_html(
+        "<b>a</b> <!-- c --> `<i>` <https://x.com>",
+        RawHtml::Strip,
+        &mut open
+      ),
+      "a  `<i>` <https://x.com>"
+    );
+    assert_eq!(
+      rewrite_html(
+        "a<script>x()</script>b<iframe src=\"y\">",
+        RawHtml::Sanitize,
+        &mut open
+      ),
+      "ab"
+    );
+    assert_eq!(open.as_deref(), Some("iframe"));
+    assert_eq!(rewrite_html("inside", RawHtml::Sanitize, &mut open), "");
+    assert_eq!(
+      rewrite_html("</IFRAME>after", RawHtml::Sanitize, &mut open),
+      "after"
+    );
+    assert_eq!(open, None);
+  }
+
+  #[test]
+  fn body_tags_skip_code_math_and_comments() {
+    let note = "#a text #real #2024\n\n    #include <stdio.h>\n    x = y #notatag\n\n\
+      $$\n#math\n$$\n```\n#fenced\n```\n- item\n\n    #nested in the item\n\n\
+      `#span` $#x$ %%#comment%% a#b";
+    let body: Vec<String> = note.lines().map(str::to_string).collect();
+    assert_eq!(body_tags(&body), ["a", "real", "nested"]);
+  }
+
+  #[test]
+  fn inline_tags_are_stripped_out_of_code_only() {
+    let note = "Text #a\n\n    #include <stdio.h>\n\n$$\n#x\n$$\n#b";
+    assert_eq!(
+      convert("inline_tags = \"strip\"", note),
+      "Text \n\n    #include <stdio.h>\n\n$$\n#x\n$$\n"
+    );
+  }
+
+  #[test]
+  fn raw_html_leaves_code_alone() {
+    let note = "<b>a</b> %%c%%\n\n    <script>x()</script> %%d%%\n\n```\n<script>\n```\n";
+    assert_eq!(
+      convert("raw_html = \"strip\"", note),
+      "a \n\n    <script>x()</script> %%d%%\n\n```\n<script>\n```\n"
+    );
+  }
+}
@@ -0,0 +1,151 @@
+use std::{
+  env,
+  fs::{self, File},
+  path::{Path, PathBuf},
+  process::{self, Command, Stdio},
+  thread,
+  time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use tracing::info;
+
+use crate::lock::is_running;
+
+/// PID file of the daemon, in the Hugo dirs it writes into
+const PID_FILE: &str = ".tecexp.pid";
+
+/// Log file of a detached daemon, in the Hugo dir
+const LOG_FILE: &str = ".tecexp.log";
+
+/// PID files of a running daemon, removed on drop
+pub struct PidFiles {
+  paths: Vec<PathBuf>,
+}
+
+impl PidFiles {
+  pub fn create(paths: Vec<PathBuf>) -> Result<Self> {
+    for path in &paths {
+      if let Some(pid) = running_pid(path) {
+        bail!("tecexp daemon is already running (pid {pid}, {path:?})");
+      }
+      fs::write(path, format!("{}\n", process::id()))
+        .with_context(|| format!("Cannot write PID file {path:?}"))?;
+    }
+    Ok(Self { paths })
+  }
+}
+
+impl Drop for PidFiles {
+  fn drop(&mut self) {
+    for path in &self.paths {
+      let _ = fs::remove_file(path);
+    }
+  }
+}
+
+/// `pid_file` if given, the PID files in the Hugo dirs otherwise
+pub fn pid_paths(pid_file: Option<&Path>, hugo_dirs: &[&PathBuf]) -> Vec<PathBuf> {
+  match pid_file {
+    Some(pid_file) => vec![pid_file.to_path_buf()],
+    None => hugo_dirs.iter().map(|dir| dir.join(PID_FILE)).collect(),
+  }
+}
+
+/// Pid of the daemon of the PID file at `path`, if it is running
+fn running_pid(path: &Path) -> Option<String> {
+  let pid = fs::read_to_string(path).ok()?.trim().to_string();
+  is_running(&pid).then_some(pid)
+}
+
+/// Print whether the daemon of the PID files runs, fail if it doesn't
+pub fn status(paths: &[PathBuf]) -> Result<()> {
+  let mut running = false;
+  for path in paths {
+    match running_pid(path) {
+      Some(pid) => {
+        println!("running (pid {pid}): {}", path.display());
+        running = true;
+      }
+      None => println!("not running: {}", path.display()),
+    }
+  }
+  if !running {
+    bail!("tecexp daemon is not running");
+  }
+  Ok(())
+}
+
+/// Ask the daemon of the PID files to stop, and wait until it has
+pub fn stop(paths: &[PathBuf]) -> Result<()> {
+  let mut pids: Vec<String> = paths.iter().filter_map(|path| running_pid(path)).collect();
+  pids.dedup();
+  if pids.is_empty() {
+    bail!("tecexp daemon is not running");
+  }
+
+  for pid in &pids {
+    info!(pid, "stop");
+    terminate(pid).with_context(|| format!("Cannot stop tecexp daemon (pid {pid})"))?;
+  }
+
+  // The daemon finishes its exports and removes its PID files
+  for _ in 0..100 {
+    if paths.iter().all(|path| running_pid(path).is_none()) {
+      return Ok(());
+    }
+    thread::sleep(Duration::from_millis(100));
+  }
+  bail!("tecexp daemon is still running after 10s")
+}
+
+/// Ask the process `pid` to terminate
+#[cfg(unix)]
+fn terminate(pid: &str) -> Result<()> {
+  use nix::{
+    sys::signal::{kill, Signal},
+    unistd::Pid,
+  };
+  kill(Pid::from_raw(pid.parse()?), Signal::SIGTERM)?;
+  Ok(())
+}
+
+/// Ask the process `pid` to terminate
+#[cfg(not(unix))]
+fn terminate(pid: &str) -> Result<()> {
+  let status = Command::new("taskkill").args(["/PID", pid]).status()?;
+  if !status.success() {
+    bail!("taskkill failed with {status}");
+  }
+  Ok(())
+}
+
+/// Run this command again in the background, without `--detach`, logging to `log_file`
+/// or the log file in `hugo_dir`
+pub fn detach(log_file: Option<&Path>, hugo_dir: &Path) -> Result<()> {
+  let log_file = log_file.map_or_else(|| hugo_dir.join(LOG_FILE), Path::to_path_buf);
+  let log = File::options()
+    .create(true)
+    .append(true)
+    .open(&log_file)
+    .with_context(|| format!("Cannot open log file {log_file:?}"))?;
+
+  let mut command = Command::new(env::current_exe()?);
+  command
+    .args(env::args_os().skip(1).filter(|arg| arg != "--detach"))
+    .env_remove("TECEXP_DETACH")
+    .stdin(Stdio::null())
+    .stdout(log.try_clone()?)
+    .stderr(log);
+  // Out of the process group of the terminal, so Ctrl-C there doesn't reach it
+  #[cfg(unix)]
+  std::os::unix::process::CommandExt::process_group(&mut command, 0);
+  let child = command.spawn().context("Cannot start the daemon")?;
+
+  println!(
+    "tecexp daemon started (pid {}), logging to {}",
+    child.id(),
+    log_file.display()
+  );
+  Ok(())
+}
@@ -0,0 +1,97 @@
+use std::time::{Duration, SystemTime};
+
+use anyhow::{anyhow, bail, Result};
+use time::{
+  format_description::well_known::Iso8601, macros::format_description, Date, Month, OffsetDateTime,
+  Time, UtcOffset,
+};
+use toml::value::{Datetime, Offset};
+
+/// Parse `--since`, a timestamp like `2024-05-12T08:00:00+02:00` or `2024-05-12`,
+/// or a duration before now like `90m`, `12h`, `2d` or `1w2d`
+pub fn parse_since(value: &str) -> Result<SystemTime> {
+  if let Ok(date_time) = OffsetDateTime::parse(value, &Iso8601::DEFAULT) {
+    return Ok(date_time.into());
+  }
+  if let Ok(date) = Date::parse(value, format_description!("[year]-[month]-[day]")) {
+    let offset = UtcOffset::current_local_offset().unwrap_or(UtcOffset::UTC);
+    return Ok(date.midnight().assume_offset(offset).into());
+  }
+
+  let duration = parse_duration(value)
+    .ok_or_else(|| anyhow!("Expect a timestamp or a duration like `12h`, got `{value}`"))?;
+  match SystemTime::now().checked_sub(duration) {
+    Some(since) => Ok(since),
+    None => bail!("Duration `{value}` is too long"),
+  }
+}
+
+/// Parse the timezone of the dates of posts: `utc`, `local` or an offset like `+02:00`
+pub fn parse_timezone(value: &str) -> Result<UtcOffset> {
+  match value {
+    "utc" | "UTC" => Ok(UtcOffset::UTC),
+    "local" => UtcOffset::current_local_offset()
+      .map_err(|_| anyhow!("Cannot determine the local UTC offset, set an offset like `+02:00`")),
+    offset => UtcOffset::parse(
+      offset,
+      format_description!("[offset_hour sign:mandatory]:[offset_minute]"),
+    )
+    .map_err(|_| anyhow!("Expect `utc`, `local` or an offset like `+02:00`, got `{offset}`")),
+  }
+}
+
+/// Parse a date written in a note or by git, like `2024-05-12`, `2024-05-12 08:00` or
+/// `2024-05-12T08:00:00+02:00`. Dates without offset are in `offset`, dates without time at midnight.
+pub fn parse_date(value: &str, offset: UtcOffset) -> Option<OffsetDateTime> {
+  let datetime: Datetime = value.parse().ok()?;
+  let date = datetime.date?;
+  let date = Date::from_calendar_date(
+    date.year.into(),
+    Month::try_from(date.month).ok()?,
+    date.day,
+  )
+  .ok()?;
+  let time = match datetime.time {
+    Some(time) => Time::from_hms_nano(
+      time.hour,
+      time.minute,
+      time.second.unwrap_or(0),
+      time.nanosecond.unwrap_or(0),
+    )
+    .ok()?,
+    None => Time::MIDNIGHT,
+  };
+  let offset = match datetime.offset {
+    Some(Offset::Z) => UtcOffset::UTC,
+    Some(Offset::Custom { minutes }) => {
+      UtcOffset::from_whole_seconds(i32::from(minutes) * 60).ok()?
+    }
+    None => offset,
+  };
+  Some(date.with_time(time).assume_offset(offset))
+}
+
+fn parse_duration(value: &str) -> Option<Duration> {
+  let mut secs: u64 = 0;
+  let mut num = String::new();
+  for c in value.trim().chars() {
+    if c.is_ascii_digit() {
+      num.push(c);
+      continue;
+    }
+    let unit = match c {
+      's' => 1,
+      'm' => 60,
+      'h' => 60 * 60,
+      'd' => 24 * 60 * 60,
+      'w' => 7 * 24 * 60 * 60,
+      _ => return None,
+    };
+    secs = secs.checked_add(num.parse::<u64>().ok()?.checked_mul(unit)?)?;
+    num.clear();
+  }
+  if !num.is_empty() || secs == 0 {
+    return None;
+  }
+  Some(Duration::from_secs(secs))
+}
@@ -0,0 +1,1540 @@
+use std::{
+  cell::RefCell,
+  collections::{BTreeMap, HashMap, HashSet},
+  ffi::OsStr,
+  fs::{self, File},
+  io::{self, BufRead, BufReader, IsTerminal, Write},
+  path::{Path, PathBuf},
+  time::SystemTime,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use tracing::{debug, info, trace, warn};
+
+use crate::{
+  backup::backup,
+  config::{Backlinks, Config},
+  convert::{
+    build_dst_props, default_link_url, public_lines, write_content, write_props, ContentRules,
+    NoteDates,
+  },
+  filter::Filter,
+  git::{commit_dates, GitDates},
+  graph::{Graph, GraphEdge, GraphNode},
+  index::NoteIndex,
+  manifest::{hash, Manifest, ManifestAsset, ManifestPost},
+  progress::Progress,
+  props::{extract_src_props, read_src_props, Prop, PropRules},
+  publish::{build_publish, Publish},
+  slug::SlugCollision,
+  template::TemplateVars,
+};
+
+/// Marks the dst dirs created by tecexp, which it may prune and delete
+const MARKER_FILE: &str = ".tecexp";
+
+/// Note whose props are the defaults of all notes beneath its folder, not exported itself
+pub const DEFAULTS_FILE: &str = "_defaults.md";
+
+/// Page of a Hugo section, written into the dirs mirroring vault folders
+const SECTION_INDEX: &str = "_index.md";
+
+/// Graph of the posts in the Hugo dir, read by themes as `site.Data.graph`
+const GRAPH_FILE: &str = "data/graph.json";
+
+/// An Obsidian vault and the Hugo dir its posts are exported to
+pub struct Vault {
+  pub src_dir: PathBuf,
+  pub asset_src: PathBuf,
+  pub dst_dir: PathBuf,
+}
+
+/// A vault folder exported to its own Hugo sub dir
+pub struct Section {
+  pub folder: PathBuf,
+  pub dst_dir: PathBuf,
+}
+
+/// Options of an export run, given on the command line
+#[derive(Debug, Clone, Default)]
+pub struct RunOptions {
+  /// Only report what would be written, copied and deleted
+  pub dry_run: bool,
+  /// Only export notes modified after this time, keeping the other posts
+  pub since: Option<SystemTime>,
+  /// Export notes and copy assets even if the dst is up to date
+  pub force: bool,
+  /// Draw a progress bar while exporting all notes
+  pub progress: bool,
+  /// Delete the posts and assets dirs without asking
+  pub yes: bool,
+}
+
+/// Counts of the files an exporter wrote, copied and deleted
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Stats {
+  pub exported: u64,
+  pub copied: u64,
+  pub deleted: u64,
+}
+
+/// Source and destination dirs of the export
+pub struct Exporter {
+  /// Name of the config profile
+  pub profile: Option<String>,
+  pub hugo_dir: PathBuf,
+  pub vaults: Vec<Vault>,
+  /// Sections, the deepest folders first
+  pub sections: Vec<Section>,
+  pub asset_dst: PathBuf,
+  /// Selects the notes to export
+  pub publish: Box<dyn Publish>,
+  /// How the props of notes become the props of posts
+  prop_rules: PropRules,
+  /// How the content of notes becomes the content of posts
+  content_rules: ContentRules,
+  /// Notes of the vaults to export
+  pub filter: Filter,
+  pub options: RunOptions,
+  /// Keep the old urls of renamed notes as aliases of their post
+  rename_aliases: bool,
+  /// Export notes into dirs mirroring their folders rather than prefixing their slugs
+  mirror_folders: bool,
+  /// Where posts list the posts linking to them
+  backlinks: Backlinks,
+  backlinks_title: String,
+  /// File the graph of the posts is written to, if any
+  graph_path: Option<PathBuf>,
+  /// Move deleted and overwritten files to the trash
+  trash: bool,
+  /// Dir of the backups taken before a clean or forced export
+  backup_dir: Option<PathBuf>,
+  backup_keep: usize,
+  /// Src note of every dst post exported so far, to detect slug collisions
+  exported: RefCell<HashMap<PathBuf, PathBuf>>,
+  /// Posts exported by this and earlier runs
+  manifest: RefCell<Manifest>,
+  manifest_path: PathBuf,
+  /// Hash of the settings, a change converts all notes again
+  config_hash: String,
+  /// Aliases of every dst post, including the old urls of renamed notes
+  aliases: RefCell<HashMap<PathBuf, Vec<String>>>,
+  /// Notes of the vaults by name, loaded when first needed
+  note_index: RefCell<Option<NoteIndex>>,
+  /// Whether the notes linked so far are published, by path
+  published_notes: RefCell<HashMap<PathBuf, bool>>,
+  /// Suffixes of the slugs of notes colliding with others, found when first needed
+  slug_suffixes: RefCell<Option<HashMap<PathBuf, usize>>>,
+  /// Commit dates of the notes by canonical path, per vault dir, loaded when first needed
+  git_dates: RefCell<HashMap<PathBuf, HashMap<PathBuf, GitDates>>>,
+  pub stats: RefCell<Stats>,
+  progress: RefCell<Option<Progress>>,
+}
+
+impl Exporter {
+  pub fn new(config: &Config, options: RunOptions) -> Result<Self> {
+    if config.vaults.is_empty() {
+      bail!("Obsidian vault dir is required (--obsidian-dir, `obsidian_dir` or `vaults`)");
+    }
+    let Some(hugo_dir) = &config.hugo_dir else {
+      bail!("Hugo dir is required (--hugo-dir or `hugo_dir`)");
+    };
+    let hugo_dir =
+      fs::canonicalize(hugo_dir).with_context(|| format!("Cannot find hugo dir {hugo_dir:?}"))?;
+
+    let mut vaults = vec![];
+    for vault in &config.vaults {
+      let src_dir = fs::canonicalize(&vault.dir)
+        .with_context(|| format!("Cannot find Obsidian vault dir {:?}", vault.dir))?;
+      let posts_dir = vault
+        .hugo_posts_dir
+        .as_ref()
+        .unwrap_or(&config.hugo_posts_dir);
+      vaults.push(Vault {
+        asset_src: src_dir.join("assets"),
+        src_dir,
+        dst_dir: hugo_dir.join(posts_dir),
+      });
+    }
+
+    let mut sections: Vec<Section> = config
+      .sections
+      .iter()
+      .map(|(folder, dst_dir)| Section {
+        folder: folder.clone(),
+        dst_dir: hugo_dir.join(dst_dir),
+      })
+      .collect();
+    sections.sort_by_key(|section| std::cmp::Reverse(section.folder.components().count()));
+
+    let manifest_path = hugo_dir.join(match &config.name {
+      Some(name) => format!(".tecexp-manifest.{name}.json"),
+      None => ".tecexp-manifest.json".to_string(),
+    });
+    let prop_rules = PropRules::new(config)?;
+    let config_hash = hash(
+      format!(
+        "{} {config:?} {:?}",
+        env!("CARGO_PKG_VERSION"),
+        prop_rules.template
+      )
+      .as_bytes(),
+    );
+
+    Ok(Self {
+      profile: config.name.clone(),
+      hugo_dir: hugo_dir.clone(),
+      vaults,
+      sections,
+      asset_dst: hugo_dir.join(&config.hugo_assets_dir),
+      publish: build_publish(config),
+      prop_rules,
+      content_rules: ContentRules::new(config),
+      filter: Filter::new(&config.include, &config.exclude)?,
+      options,
+      rename_aliases: config.rename_aliases,
+      mirror_folders: config.mirror_folders,
+      backlinks: config.backlinks,
+      backlinks_title: config.backlinks_title.clone(),
+      graph_path: config.graph.then(|| hugo_dir.join(GRAPH_FILE)),
+      trash: config.trash,
+      backup_dir: config.backup_dir.as_ref().map(|dir| hugo_dir.join(dir)),
+      backup_keep: config.backup_keep,
+      exported: RefCell::new(HashMap::new()),
+      manifest: RefCell::new(Manifest::load(&manifest_path)),
+      manifest_path,
+      config_hash,
+      aliases: RefCell::new(HashMap::new()),
+      note_index: RefCell::new(None),
+      published_notes: RefCell::new(HashMap::new()),
+      slug_suffixes: RefCell::new(None),
+      git_dates: RefCell::new(HashMap::new()),
+      stats: RefCell::new(Stats::default()),
+      progress: RefCell::new(None),
+    })
+  }
+
+  /// Posts dirs of all vaults, section dirs and the assets dir, without duplicates
+  fn dst_dirs(&self) -> Vec<&PathBuf> {
+    let mut dirs: Vec<&PathBuf> = vec![];
+    let vault_dirs = self.vaults.iter().map(|vault| &vault.dst_dir);
+    let section_dirs = self.sections.iter().map(|section| &section.dst_dir);
+    for dir in vault_dirs.chain(section_dirs) {
+      if !dirs.contains(&dir) {
+        dirs.push(dir);
+      }
+    }
+    dirs.push(&self.asset_dst);
+    dirs
+  }
+
+  /// Remove the posts and assets dirs
+  pub fn clean(&self) -> Result<()> {
+    let dirs: Vec<&PathBuf> = self
+      .dst_dirs()
+      .into_iter()
+      .filter(|dir| dir.exists())
+      .collect();
+    for dir in &dirs {
+      if !is_owned(dir) {
+        bail!(
+          "Refusing to delete {dir:?}, it has no {MARKER_FILE} marker and may not be created by tecexp"
+        );
+      }
+    }
+    if !self.options.dry_run && !self.options.yes {
+      confirm_delete(&dirs)?;
+    }
+    self.backup()?;
+
+    for dir in dirs {
+      info!(dir = %dir.display(), "delete");
+      if !self.options.dry_run {
+        self.delete(dir)?;
+      }
+    }
+    *self.manifest.borrow_mut() = Manifest::default();
+    if !self.options.dry_run && self.manifest_path.exists() {
+      fs::remove_file(&self.manifest_path)?;
+    }
+    Ok(())
+  }
+
+  /// Write the manifest of the exported posts for the next runs, and the graph of the posts
+  pub fn save_manifest(&self) -> Result<()> {
+    if self.options.dry_run {
+      return Ok(());
+    }
+    self.save_graph()?;
+    let mut manifest = self.manifest.borrow_mut();
+    manifest.config = self.config_hash.clone();
+    manifest.save(&self.manifest_path)
+  }
+
+  /// The posts of the manifest and the links between them, by url
+  fn graph(&self) -> Graph {
+    let manifest = self.manifest.borrow();
+    let urls: HashMap<&Path, String> = manifest
+      .posts
+      .iter()
+      .filter_map(|(dst, post)| Some((post.src.as_path(), self.post_url(dst)?)))
+      .collect();
+    let mut graph = Graph::default();
+    for post in manifest.posts.values() {
+      let (Some(url), Some(title)) = (urls.get(post.src.as_path()), note_title(&post.src)) else {
+        continue;
+      };
+      graph.nodes.push(GraphNode {
+        id: url.clone(),
+        title,
+        url: url.clone(),
+      });
+      for note in &post.linked_notes {
+        match urls.get(note.as_path()) {
+          Some(target) if target != url => graph.edges.push(GraphEdge {
+            source: url.clone(),
+            target: target.clone(),
+          }),
+          _ => {}
+        }
+      }
+    }
+    graph
+  }
+
+  /// Write the graph of the posts into the Hugo data dir if asked and changed,
+  /// not to rebuild the site for nothing
+  fn save_graph(&self) -> Result<()> {
+    let Some(path) = &self.graph_path else {
+      return Ok(());
+    };
+    let text = serde_json::to_string_pretty(&self.graph())?;
+    if fs::read_to_string(path).is_ok_and(|old| old == text) {
+      return Ok(());
+    }
+    debug!(graph = %path.display(), "write");
+    if let Some(dir) = path.parent() {
+      fs::create_dir_all(dir)?;
+    }
+    fs::write(path, text).with_context(|| format!("Cannot write graph {path:?}"))
+  }
+
+  /// Back the dst dirs holding more than the marker up, if a backup dir is set
+  fn backup(&self) -> Result<()> {
+    let Some(backup_dir) = self.backup_dir.as_ref().filter(|_| !self.options.dry_run) else {
+      return Ok(());
+    };
+    let dirs: Vec<&PathBuf> = self
+      .dst_dirs()
+      .into_iter()
+      .filter(|dir| {
+        fs::read_dir(dir).is_ok_and(|mut entries| {
+          entries.any(|entry| entry.is_ok_and(|entry| entry.file_name() != MARKER_FILE))
+        })
+      })
+      .collect();
+    backup(&self.hugo_dir, &dirs, backup_dir, self.backup_keep)
+  }
+
+  /// Create the missing dst dirs, marking the new and empty ones as owned by tecexp
+  fn create_dst_dirs(&self) -> Result<()> {
+    if !self.options.dry_run {
+      for dir in self.dst_dirs() {
+        fs::create_dir_all(dir)?;
+        if fs::read_dir(dir)?.next().is_none() {
+          fs::write(dir.join(MARKER_FILE), "")?;
+        }
+      }
+    }
+    Ok(())
+  }
+
+  /// Export all notes of the vaults, then delete the posts and assets no note maps to
+  pub fn export_all(&self) -> Result<()> {
+    // Notes may have been committed, created or renamed since
+    self.git_dates.borrow_mut().clear();
+    self.reset_note_index();
+    let published = self.manifest_names();
+    if self.options.force {
+      self.backup()?;
+    }
+    self.create_dst_dirs()?;
+
+    if self.options.progress {
+      // Count the notes first, for the length of the progress bar
+      let mut len = 0;
+      for vault in &self.vaults {
+        recursive_scan(&vault.src_dir, Path::new(""), &self.filter, &mut |_| {
+          len += 1;
+          Ok(())
+        })?;
+      }
+      *self.progress.borrow_mut() = Some(Progress::new(len));
+    }
+
+    for vault in &self.vaults {
+      recursive_scan(
+        &vault.src_dir,
+        Path::new(""),
+        &self.filter,
+        &mut |sub_path| self.export_note(vault, sub_path),
+      )?;
+    }
+    self.progress.borrow_mut().take();
+
+    self.prune()?;
+    // Links to the notes published, unpublished or renamed since the last run point elsewhere,
+    // though the notes linking to them are unchanged
+    let changed: HashSet<String> = self
+      .published_names()
+      .symmetric_difference(&published)
+      .map(|(name, _)| name.clone())
+      .collect();
+    if !changed.is_empty() {
+      self.export_backlinks(&changed)?;
+    }
+    // The links of all notes are known now
+    self.update_backlinks()?;
+    self.save_manifest()
+  }
+
+  /// Delete the files of the dst dirs that were not exported, and forget their notes
+  fn prune(&self) -> Result<()> {
+    let exported = self.exported.borrow();
+    let mut manifest = self.manifest.borrow_mut();
+    manifest.posts.retain(|dst, _| exported.contains_key(dst));
+    // An export since a time skips the older notes, only the manifest knows their assets
+    let with_assets =
+      self.options.since.is_none() || exported.keys().all(|dst| manifest.posts.contains_key(dst));
+    let assets: Vec<&PathBuf> = manifest
+      .posts
+      .values()
+      .flat_map(|post| post.assets.iter().map(|asset| &asset.dst))
+      .collect();
+
+    for dir in self.dst_dirs() {
+      if !dir.is_dir() || (!with_assets && *dir == self.asset_dst) {
+        continue;
+      }
+      if !is_owned(dir) {
+        warn!(
+          dir = %dir.display(),
+          "not pruned, add a {MARKER_FILE} file if tecexp may delete its stale files"
+        );
+        continue;
+      }
+      self.prune_dir(dir, &exported, &assets)?;
+    }
+    Ok(())
+  }
+
+  /// Delete the files of `dir` that were not exported, and the dirs of folders left empty
+  fn prune_dir(
+    &self,
+    dir: &Path,
+    exported: &HashMap<PathBuf, PathBuf>,
+    assets: &[&PathBuf],
+  ) -> Result<()> {
+    let dst_dirs = self.dst_dirs();
+    for res in fs::read_dir(dir)? {
+      let entry = res?;
+      let path = entry.path();
+      let name = entry.file_name();
+      if path.is_dir() {
+        // Section dirs may be nested in the posts dir, they are pruned on their own
+        if !is_owned(&path) || dst_dirs.contains(&&path) {
+          continue;
+        }
+        self.prune_dir(&path, exported, assets)?;
+        let is_empty = fs::read_dir(&path)?.map_while(Result::ok).all(|entry| {
+          let name = entry.file_name();
+          name == MARKER_FILE || name == SECTION_INDEX
+        });
+        if is_empty {
+          info!(dst = %path.display(), "delete");
+          self.stats.borrow_mut().deleted += 1;
+          if !self.options.dry_run {
+            self.delete(&path)?;
+          }
+        }
+        continue;
+      }
+      // The marker prefixes the names of the lock and manifests too,
+      // the dirs of folders keep their section page
+      let is_folder_dir = !dst_dirs.iter().any(|dst_dir| dst_dir.as_path() == dir);
+      if name.to_string_lossy().starts_with(MARKER_FILE)
+        || (name == SECTION_INDEX && is_folder_dir)
+        || exported.contains_key(&path)
+        || assets.contains(&&path)
+      {
+        continue;
+      }
+      info!(dst = %path.display(), "delete");
+      self.stats.borrow_mut().deleted += 1;
+      if !self.options.dry_run {
+        self.delete(&path)?;
+      }
+    }
+    Ok(())
+  }
+
+  /// Export the given notes only, keeping the rest of the posts and assets dirs
+  pub fn export_files(&self, files: &[PathBuf]) -> Result<()> {
+    if self.options.force {
+      self.backup()?;
+    }
+    self.create_dst_dirs()?;
+
+    for file in files {
+      let (vault, sub_path) = self.vault_sub_path(file)?;
+      self.export_note(vault, &sub_path)?;
+    }
+    self.update_backlinks()?;
+    self.save_manifest()
+  }
+
+  /// Resolve a note path given relative to a vault dir, or to the working dir
+  fn vault_sub_path(&self, file: &Path) -> Result<(&Vault, PathBuf)> {
+    let full_path = self
+      .vaults
+      .iter()
+      .map(|vault| vault.src_dir.join(file))
+      .find(|full_path| full_path.is_file())
+      .unwrap_or_else(|| file.to_path_buf());
+    let full_path =
+      fs::canonicalize(&full_path).with_context(|| format!("Cannot find note {file:?}"))?;
+
+    if full_path.extension() != Some(OsStr::new("md")) {
+      bail!("Note {file:?} is not a md file");
+    }
+    self
+      .vault_of(&full_path)
+      .ok_or_else(|| anyhow!("Note {file:?} is not in an Obsidian vault dir"))
+  }
+
+  /// The vault containing `full_path` and the path relative to the vault dir
+  pub fn vault_of(&self, full_path: &Path) -> Option<(&Vault, PathBuf)> {
+    self.vaults.iter().find_map(|vault| {
+      let sub_path = full_path.strip_prefix(&vault.src_dir).ok()?;
+      Some((vault, sub_path.to_path_buf()))
+    })
+  }
+
+  /// Export the note at `sub_path`, relative to the vault dir
+  pub fn export_note(&self, vault: &Vault, sub_path: &Path) -> Result<()> {
+    self.export(
+      vault,
+      &vault.src_dir.join(sub_path),
+      &self.dst_path(vault, sub_path),
+      self.options.force,
+    )
+  }
+
+  /// Url names of the published notes, as `[[links]]` to them are resolved, with their posts
+  pub fn published_names(&self) -> HashSet<(String, PathBuf)> {
+    self
+      .exported
+      .borrow()
+      .iter()
+      .filter_map(|(dst, src)| self.url_name(src, dst))
+      .collect()
+  }
+
+  /// Url names of the notes published when the manifest was saved, with their posts
+  fn manifest_names(&self) -> HashSet<(String, PathBuf)> {
+    self
+      .manifest
+      .borrow()
+      .posts
+      .iter()
+      .filter_map(|(dst, post)| self.url_name(&post.src, dst))
+      .collect()
+  }
+
+  /// Url name of the note `src`, with its post `dst`
+  fn url_name(&self, src: &Path, dst: &Path) -> Option<(String, PathBuf)> {
+    let name = self.content_rules.slug.to_url(src.file_stem()?.to_str()?);
+    Some((name, dst.to_path_buf()))
+  }
+
+  /// The notes linking to or embedding the note `src` and their posts, by title,
+  /// none if posts don't list them
+  fn backlinks(&self, src: &Path) -> Vec<(PathBuf, PathBuf)> {
+    if self.backlinks == Backlinks::None {
+      return vec![];
+    }
+    let mut backlinks: Vec<(PathBuf, PathBuf)> = self
+      .manifest
+      .borrow()
+      .posts
+      .iter()
+      .filter(|(_, post)| post.src != src && post.linked_notes.iter().any(|note| note == src))
+      .map(|(dst, post)| (post.src.clone(), dst.clone()))
+      .collect();
+    backlinks.sort_by_key(|(src, _)| (note_title(src), src.clone()));
+    backlinks
+  }
+
+  /// Export again the notes linked from other notes than when they were exported,
+  /// for their posts to list the new ones
+  pub fn update_backlinks(&self) -> Result<()> {
+    if self.backlinks == Backlinks::None {
+      return Ok(());
+    }
+    let srcs: Vec<PathBuf> = self
+      .manifest
+      .borrow()
+      .posts
+      .values()
+      .filter(|post| {
+        let backlinks = self.backlinks(&post.src);
+        !backlinks.iter().map(|(src, _)| src).eq(&post.backlinks)
+      })
+      .map(|post| post.src.clone())
+      .collect();
+    for src in srcs {
+      if let Some((vault, sub_path)) = self.vault_of(&src).filter(|_| src.is_file()) {
+        debug!(src = %src.display(), "export backlinks");
+        self.export_note(vault, &sub_path)?;
+      }
+    }
+    Ok(())
+  }
+
+  /// Log the links of the exported posts to notes found in no vault, returning their number.
+  /// Notes created since the posts were exported fix their links.
+  pub fn report_broken_links(&self) -> Result<usize> {
+    let posts: Vec<(PathBuf, Vec<String>)> = self
+      .manifest
+      .borrow()
+      .posts
+      .values()
+      .filter(|post| !post.broken_links.is_empty())
+      .map(|post| (post.src.clone(), post.broken_links.clone()))
+      .collect();
+    let mut count = 0;
+    for (src, names) in posts {
+      for name in names {
+        if self.find_note(&src, &name)?.is_none() {
+          warn!(src = %src.display(), link = name, "broken link");
+          count += 1;
+        }
+      }
+    }
+    if count > 0 {
+      warn!(count, "broken links");
+    }
+    Ok(count)
+  }
+
+  /// Export again the notes linking to the notes named `names`,
+  /// e.g. after these notes were published, unpublished or renamed
+  pub fn export_backlinks(&self, names: &HashSet<String>) -> Result<()> {
+    let srcs: Vec<PathBuf> = self
+      .manifest
+      .borrow_mut()
+      .posts
+      .values_mut()
+      .filter(|post| post.links.iter().any(|link| names.contains(link)))
+      .map(|post| {
+        // Convert the note again though its content is the same
+        post.hash.clear();
+        post.src.clone()
+      })
+      .collect();
+
+    for src in srcs {
+      if let Some((vault, sub_path)) = self.vault_of(&src).filter(|_| src.is_file()) {
+        debug!(src = %src.display(), "export backlink");
+        self.export_note(vault, &sub_path)?;
+      }
+    }
+    Ok(())
+  }
+
+  /// Copy the asset at `full_path` again to the posts referencing it,
+  /// then rewrite these posts too if `export_notes`
+  pub fn update_asset(&self, full_path: &Path, export_notes: bool) -> Result<()> {
+    let posts: Vec<(PathBuf, ManifestPost)> = self
+      .manifest
+      .borrow()
+      .posts
+      .iter()
+      .filter(|(_, post)| post.assets.iter().any(|asset| asset.src == full_path))
+      .map(|(dst, post)| (dst.clone(), post.clone()))
+      .collect();
+
+    for (dst, post) in posts {
+      for asset in post.assets.iter().filter(|asset| asset.src == full_path) {
+        self.copy_asset(&asset.src, &asset.dst)?;
+      }
+      if export_notes {
+        if let Some((vault, _)) = self.vault_of(&post.src) {
+          self.export(vault, &post.src, &dst, true)?;
+        }
+      }
+    }
+    Ok(())
+  }
+
+  /// Whether `full_path` is in the assets dir of a vault
+  pub fn is_asset(&self, full_path: &Path) -> bool {
+    self
+      .vaults
+      .iter()
+      .any(|vault| full_path.starts_with(&vault.asset_src))
+  }
+
+  /// Export the notes of the dir at `full_path`, e.g. a folder created in or moved into a vault
+  pub fn export_dir(&self, full_path: &Path) -> Result<()> {
+    match self.vault_of(full_path) {
+      Some((vault, sub_dir)) if !is_hidden(&sub_dir) && self.filter.scan_dir(&sub_dir) => {
+        recursive_scan(&vault.src_dir, &sub_dir, &self.filter, &mut |sub_path| {
+          self.export_note(vault, sub_path)
+        })
+      }
+      _ => Ok(()),
+    }
+  }
+
+  /// Delete the posts of the notes that were in the dir at `full_path`,
+  /// e.g. a folder deleted or moved out of a vault
+  pub fn remove_dir(&self, full_path: &Path) -> Result<()> {
+    let Some((vault, _)) = self.vault_of(full_path) else {
+      return Ok(());
+    };
+    let srcs: Vec<PathBuf> = self
+      .exported
+      .borrow()
+      .values()
+      .filter(|src| src.starts_with(full_path))
+      .cloned()
+      .collect();
+    for src in srcs {
+      if let Ok(sub_path) = src.strip_prefix(&vault.src_dir) {
+        self.remove_note(vault, sub_path)?;
+      }
+    }
+    Ok(())
+  }
+
+  /// Dst post of the note at `sub_path`, in the dir of its section if any,
+  /// with a suffix if it collides with the post of another note and the rules say so
+  pub fn dst_path(&self, vault: &Vault, sub_path: &Path) -> PathBuf {
+    let dst = self.slug_dst_path(vault, sub_path);
+    if self.content_rules.slug.collision != SlugCollision::Suffix {
+      return dst;
+    }
+    let full_path = vault.src_dir.join(sub_path);
+    // A deleted note had the post the manifest says
+    if !full_path.exists() {
+      let manifest = self.manifest.borrow();
+      let post = manifest
+        .posts
+        .iter()
+        .find(|(_, post)| post.src == full_path);
+      if let Some((dst, _)) = post {
+        return dst.clone();
+      }
+    }
+    match self.slug_suffix(&full_path) {
+      Some(suffix) => {
+        let stem = dst.file_stem().and_then(OsStr::to_str).unwrap_or_default();
+        dst.with_file_name(format!("{stem}-{suffix}.md"))
+      }
+      None => dst,
+    }
+  }
+
+  /// The suffix of the slug of the note at `full_path`, if another note has the same slug
+  fn slug_suffix(&self, full_path: &Path) -> Option<usize> {
+    let mut suffixes = self.slug_suffixes.borrow_mut();
+    let suffixes = suffixes.get_or_insert_with(|| self.find_slug_collisions());
+    suffixes.get(full_path).copied()
+  }
+
+  /// Suffixes of the notes of the vaults sharing their slug with other notes, by path.
+  /// The note already exported to the post keeps it, the others get `-2`, `-3`...
+  fn find_slug_collisions(&self) -> HashMap<PathBuf, usize> {
+    let mut by_dst: HashMap<PathBuf, Vec<PathBuf>> = HashMap::new();
+    for vault in &self.vaults {
+      let scan = recursive_scan(
+        &vault.src_dir,
+        Path::new(""),
+        &self.filter,
+        &mut |sub_path| {
+          by_dst
+            .entry(self.slug_dst_path(vault, sub_path))
+            .or_default()
+            .push(vault.src_dir.join(sub_path));
+          Ok(())
+        },
+      );
+      if let Err(error) = scan {
+        warn!(dir = %vault.src_dir.display(), %error, "cannot scan for slug collisions");
+      }
+    }
+
+    let manifest = self.manifest.borrow();
+    let mut suffixes = HashMap::new();
+    for (dst, mut srcs) in by_dst.into_iter().filter(|(_, srcs)| srcs.len() > 1) {
+      let owner = manifest.posts.get(&dst).map(|post| &post.src);
+      srcs.sort_by(|a, b| (Some(a) != owner, a).cmp(&(Some(b) != owner, b)));
+      for (i, src) in srcs.into_iter().enumerate().skip(1) {
+        debug!(src = %src.display(), dst = %dst.display(), suffix = i + 1, "slug collision");
+        suffixes.insert(src, i + 1);
+      }
+    }
+    suffixes
+  }
+
+  /// Dst post of the note at `sub_path` named after its slug only
+  fn slug_dst_path(&self, vault: &Vault, sub_path: &Path) -> PathBuf {
+    let (dst_dir, rest) = self.post_root(vault, sub_path);
+    let slug = &self.content_rules.slug;
+    if !self.mirror_folders {
+      return dst_dir.join(slug.to_url(rest.to_str().unwrap()));
+    }
+    let mut dst = dst_dir.to_path_buf();
+    for name in rest {
+      dst.push(slug.to_url(&name.to_string_lossy()));
+    }
+    dst
+  }
+
+  /// The dir of the posts of the vault or section the note at `sub_path` is in,
+  /// and the path of the note in the vault or section folder
+  fn post_root<'a>(&'a self, vault: &'a Vault, sub_path: &'a Path) -> (&'a Path, &'a Path) {
+    for section in &self.sections {
+      if let Ok(rest) = sub_path.strip_prefix(&section.folder) {
+        return (&section.dst_dir, rest);
+      }
+    }
+    (&vault.dst_dir, sub_path)
+  }
+
+  /// Create the dirs of the post of the note at `sub_path` mirroring its folders,
+  /// with an `_index.md` titled after the folder for Hugo to make them sections
+  fn create_section_dirs(&self, vault: &Vault, sub_path: &Path) -> Result<()> {
+    let (dst_dir, rest) = self.post_root(vault, sub_path);
+    let mut dir = dst_dir.to_path_buf();
+    for folder in rest.parent().into_iter().flat_map(Path::iter) {
+      let title = folder.to_string_lossy();
+      dir.push(self.content_rules.slug.to_url(&title));
+      if dir.is_dir() {
+        continue;
+      }
+      fs::create_dir(&dir)?;
+      fs::write(dir.join(MARKER_FILE), "")?;
+      let props = BTreeMap::from([("title".to_string(), Prop::Str(title.into_owned()))]);
+      let mut index = vec![];
+      write_props(&mut index, &props, self.prop_rules.format)?;
+      fs::write(dir.join(SECTION_INDEX), index)?;
+    }
+    Ok(())
+  }
+
+  /// Export the note `src` to `dst`, even if the post is up to date if `force`
+  fn export(&self, vault: &Vault, src: &Path, dst: &Path, force: bool) -> Result<()> {
+    let src_file = File::open(src)?;
+    let mut src_lines = BufReader::new(src_file)
+      .lines()
+      .map_while(Result::ok)
+      .peekable();
+
+    // Extract src props
+    let src_props = match extract_src_props(&mut src_lines) {
+      Ok(src_props) => src_props,
+      Err(error) => {
+        // Keep the post of a note whose frontmatter is being edited until it is valid again
+        warn!(src = %src.display(), error = %error, "invalid frontmatter");
+        self
+          .exported
+          .borrow_mut()
+          .entry(dst.to_path_buf())
+          .or_insert_with(|| src.to_path_buf());
+        self.skip(src, "invalid frontmatter");
+        return Ok(());
+      }
+    };
+    if let Some(src_props) = src_props {
+      if !self.publish.is_published(&src_props) {
+        self.remove_post(src, dst)?;
+        self.skip(src, "unpublished");
+        return Ok(());
+      }
+
+      let mut exported = self.exported.borrow_mut();
+      if let Some(other) = exported.get(dst).filter(|other| *other != src) {
+        bail!(
+          "{src:?} and {other:?} are both exported to {dst:?}, \
+           rename one or set `slug_collision = \"suffix\"`"
+        );
+      }
+      exported.insert(dst.to_path_buf(), src.to_path_buf());
+      drop(exported);
+
+      if let Some(since) = self.options.since {
+        if fs::metadata(src)?.modified()? <= since {
+          self.skip(src, "unchanged since");
+          return Ok(());
+        }
+      }
+
+      // Notes are converted again when the defaults of their folders change
+      let defaults = self.note_defaults(vault, src);
+      let mut note = fs::read(src)?;
+      if !defaults.is_empty() {
+        note.extend(format!("{defaults:?}").into_bytes());
+      }
+      // And when the notes linking to them change, if posts list them
+      let backlinks = self.backlinks(src);
+      if !backlinks.is_empty() {
+        note.extend(format!("{backlinks:?}").into_bytes());
+      }
+      // And when the notes they embed change
+      let old_embeds = self
+        .manifest
+        .borrow()
+        .posts
+        .get(dst)
+        .map(|post| post.embeds.clone())
+        .unwrap_or_default();
+      let hash = note_hash(&note, &old_embeds);
+      if let Some(post) = self.unchanged_post(src, dst, &hash).filter(|_| !force) {
+        for asset in &post.assets {
+          self.copy_asset(&asset.src, &asset.dst)?;
+        }
+        self.skip(src, "unchanged");
+        return Ok(());
+      }
+
+      // Build dst props
+      let title = src
+        .file_name()
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .trim_end_matches(".md");
+      let src_modified = fs::metadata(src)?.modified()?;
+      let git_dates = self.note_git_dates(vault, src)?;
+      let dates = NoteDates {
+        modified: src_modified.into(),
+        git: git_dates.as_ref(),
+        name: Some(title),
+      };
+      let body = public_lines(src_lines.collect(), &self.content_rules);
+      let mut assets = vec![];
+      let mut on_asset = |name: &str, url: &str| {
+        let asset = ManifestAsset {
+          src: vault.asset_src.join(name),
+          dst: self.asset_dst.join(url),
+        };
+        if !assets.contains(&asset) {
+          assets.push(asset);
+        }
+        Ok(())
+      };
+      let mut dst_props = build_dst_props(
+        &src_props,
+        &defaults,
+        &body,
+        &self.prop_rules,
+        Some(title),
+        &dates,
+        &mut on_asset,
+      )?;
+      let mut aliases = self.note_aliases(&src_props, dst);
+      if self.rename_aliases {
+        for alias in self.post_aliases(dst)? {
+          if !aliases.contains(&alias) {
+            aliases.push(alias);
+          }
+        }
+      }
+      if !aliases.is_empty() {
+        dst_props.insert("aliases".to_string(), Prop::Vec(aliases));
+      }
+      let backlink_posts: Vec<(String, String)> = backlinks
+        .iter()
+        .filter_map(|(src, dst)| Some((note_title(src)?, self.post_url(dst)?)))
+        .collect();
+      if self.backlinks == Backlinks::Props && !backlink_posts.is_empty() {
+        let posts = backlink_posts
+          .iter()
+          .map(|(title, url)| {
+            let mut post = serde_yaml::Mapping::new();
+            post.insert("title".into(), title.as_str().into());
+            post.insert("url".into(), url.as_str().into());
+            serde_yaml::Value::Mapping(post)
+          })
+          .collect();
+        dst_props.insert(
+          "backlinks".to_string(),
+          Prop::Nested(serde_yaml::Value::Sequence(posts)),
+        );
+      }
+
+      // Convert in memory, to only write the posts that change
+      let mut content = vec![];
+      let mut links = vec![];
+      match &self.prop_rules.template {
+        Some(template) => {
+          let path = src.strip_prefix(&vault.src_dir).unwrap_or(src);
+          let slug = dst.file_stem().and_then(OsStr::to_str).unwrap_or_default();
+          let vars = TemplateVars {
+            note: &src_props,
+            props: &dst_props,
+            path: path.to_str().unwrap_or_default(),
+            slug,
+            date: dst_props.get("date"),
+            lastmod: dst_props.get("lastmod"),
+          };
+          template.render(&mut content, &vars)?;
+        }
+        None => write_props(&mut content, &dst_props, self.prop_rules.format)?,
+      }
+      let mut embeds = vec![];
+      // Both links and embeds can be broken
+      let broken_links = RefCell::new(vec![]);
+      let linked_notes = RefCell::new(vec![]);
+      let check_link = |name: &str| -> Result<()> {
+        match self.find_note(src, name)? {
+          Some(path) => {
+            let mut linked = linked_notes.borrow_mut();
+            if !linked.contains(&path) {
+              linked.push(path);
+            }
+          }
+          None => {
+            let mut broken = broken_links.borrow_mut();
+            if !broken.iter().any(|link| link == name) {
+              broken.push(name.to_string());
+            }
+          }
+        }
+        Ok(())
+      };
+      write_content(
+        &mut content,
+        body.into_iter(),
+        &self.content_rules,
+        &mut on_asset,
+        &mut |name| {
+          check_link(name)?;
+          let (linked, url) = self.link_url(src, name)?;
+          if !links.contains(&linked) {
+            links.push(linked);
+          }
+          Ok(url)
+        },
+        &mut |name| {
+          check_link(name)?;
+          self.embed_lines(src, name, &mut embeds)
+        },
+      )?;
+      if self.backlinks == Backlinks::Section && !backlink_posts.is_empty() {
+        writeln!(content, "\n## {}\n", self.backlinks_title)?;
+        for (title, url) in &backlink_posts {
+          writeln!(content, "- [{title}]({url})")?;
+        }
+      }
+      let hash = if embeds == old_embeds {
+        hash
+      } else {
+        note_hash(&note, &embeds)
+      };
+
+      for asset in &assets {
+        self.copy_asset(&asset.src, &asset.dst)?;
+      }
+      let post = ManifestPost {
+        src: src.to_path_buf(),
+        hash,
+        assets,
+        links,
+        embeds,
+        linked_notes: linked_notes.into_inner(),
+        backlinks: backlinks.into_iter().map(|(src, _)| src).collect(),
+        broken_links: broken_links.into_inner(),
+      };
+      self
+        .manifest
+        .borrow_mut()
+        .posts
+        .insert(dst.to_path_buf(), post);
+
+      if !force && fs::read(dst).is_ok_and(|old| old == content) {
+        self.skip(src, "unchanged");
+        return Ok(());
+      }
+
+      info!(src = %src.display(), dst = %dst.display(), "export");
+      self.stats.borrow_mut().exported += 1;
+      if let Some(progress) = self.progress.borrow().as_ref() {
+        progress.exported();
+      }
+      if !self.options.dry_run {
+        if self.mirror_folders {
+          let sub_path = src.strip_prefix(&vault.src_dir)?;
+          self.create_section_dirs(vault, sub_path)?;
+        }
+        self.trash_old(dst)?;
+        fs::write(dst, content)?;
+        set_modified(dst, src_modified)?;
+      }
+    } else {
+      self.remove_post(src, dst)?;
+      self.skip(src, "no frontmatter");
+    }
+
+    Ok(())
+  }
+
+  /// Body lines of the note named `name` embedded in the note `src`, if it is published,
+  /// adding its path to `embeds`
+  fn embed_lines(
+    &self,
+    src: &Path,
+    name: &str,
+    embeds: &mut Vec<PathBuf>,
+  ) -> Result<Option<Vec<String>>> {
+    let Some(path) = self.find_note(src, name)? else {
+      return Ok(None);
+    };
+    if path == src {
+      return Ok(None);
+    }
+    let mut lines = BufReader::new(File::open(&path)?)
+      .lines()
+      .map_while(Result::ok)
+      .peekable();
+    match extract_src_props(&mut lines) {
+      Ok(Some(props)) if self.publish.is_published(&props) => {}
+      // Unpublished notes are private, embedding them must not publish them
+      _ => return Ok(None),
+    }
+    if !embeds.contains(&path) {
+      embeds.push(path);
+    }
+    Ok(Some(lines.collect()))
+  }
+
+  /// Path of the note `[[name]]` in the note `src` links to
+  fn find_note(&self, src: &Path, name: &str) -> Result<Option<PathBuf>> {
+    let mut index = self.note_index.borrow_mut();
+    if index.is_none() {
+      let mut notes = NoteIndex::default();
+      for vault in &self.vaults {
+        recursive_scan(
+          &vault.src_dir,
+          Path::new(""),
+          &self.filter,
+          &mut |sub_path| {
+            notes.insert(sub_path, vault.src_dir.join(sub_path));
+            Ok(())
+          },
+        )?;
+      }
+      *index = Some(notes);
+    }
+    Ok(
+      index
+        .as_ref()
+        .and_then(|notes| notes.resolve(name, Some(src)))
+        .map(Path::to_path_buf),
+    )
+  }
+
+  /// Url name of the note `[[name]]` in the note `src` links to,
+  /// and the url of its post if it is published
+  fn link_url(&self, src: &Path, name: &str) -> Result<(String, Option<String>)> {
+    let slug = &self.content_rules.slug;
+    let Some(full_path) = self.find_note(src, name)? else {
+      let stem = name.rsplit('/').next().unwrap_or(name);
+      return Ok((
+        slug.to_url(stem),
+        Some(default_link_url(name, &self.content_rules)),
+      ));
+    };
+    let post = self.vault_of(&full_path).and_then(|(vault, sub_path)| {
+      let url = self.post_url(&self.dst_path(vault, &sub_path))?;
+      Some((slug.to_url(sub_path.file_stem()?.to_str()?), url))
+    });
+    let Some((linked, url)) = post else {
+      return Ok((
+        slug.to_url(name),
+        Some(default_link_url(name, &self.content_rules)),
+      ));
+    };
+    Ok((linked, self.is_note_published(&full_path).then_some(url)))
+  }
+
+  /// Whether the note at `full_path` is published, read once per batch of notes
+  fn is_note_published(&self, full_path: &Path) -> bool {
+    if let Some(published) = self.published_notes.borrow().get(full_path) {
+      return *published;
+    }
+    let published = match read_src_props(full_path) {
+      Ok(props) => props.is_some_and(|props| self.publish.is_published(&props)),
+      // The post of a note with invalid frontmatter is kept
+      Err(_) => true,
+    };
+    self
+      .published_notes
+      .borrow_mut()
+      .insert(full_path.to_path_buf(), published);
+    published
+  }
+
+  /// Forget the notes of the vaults, e.g. after notes were created, renamed or deleted
+  pub fn reset_note_index(&self) {
+    self.note_index.borrow_mut().take();
+    self.published_notes.borrow_mut().clear();
+    self.slug_suffixes.borrow_mut().take();
+  }
+
+  /// Export again the notes embedding the note at `src`, after it changed
+  pub fn export_embedding(&self, src: &Path) -> Result<()> {
+    let srcs: Vec<PathBuf> = self
+      .manifest
+      .borrow()
+      .posts
+      .values()
+      .filter(|post| post.embeds.iter().any(|embed| embed == src))
+      .map(|post| post.src.clone())
+      .collect();
+    for src in srcs {
+      if let Some((vault, sub_path)) = self.vault_of(&src).filter(|_| src.is_file()) {
+        debug!(src = %src.display(), "export embedding");
+        self.export_note(vault, &sub_path)?;
+      }
+    }
+    Ok(())
+  }
+
+  /// Props the note at `src` inherits from the `cascade` config and the `_defaults.md`
+  /// of its folders, the deepest folders winning
+  fn note_defaults(&self, vault: &Vault, src: &Path) -> BTreeMap<String, Prop> {
+    let mut defaults = BTreeMap::new();
+    let Ok(sub_path) = src.strip_prefix(&vault.src_dir) else {
+      return defaults;
+    };
+    let mut folders: Vec<&Path> = sub_path.ancestors().skip(1).collect();
+    folders.reverse();
+    for folder in folders {
+      if let Some(props) = self.prop_rules.cascade.get(folder) {
+        defaults.extend(props.clone());
+      }
+      let defaults_file = vault.src_dir.join(folder).join(DEFAULTS_FILE);
+      if !defaults_file.is_file() {
+        continue;
+      }
+      match read_src_props(&defaults_file) {
+        Ok(props) => defaults.extend(props.unwrap_or_default()),
+        Err(error) => warn!(
+          src = %defaults_file.display(),
+          error = format!("{error:#}"),
+          "invalid defaults"
+        ),
+      }
+    }
+    defaults
+  }
+
+  /// Commit dates of the note at `src`, if the dates are taken from git and it is committed
+  fn note_git_dates(&self, vault: &Vault, src: &Path) -> Result<Option<GitDates>> {
+    let rules = &self.prop_rules;
+    if !rules
+      .date_from
+      .iter()
+      .chain(&rules.lastmod_from)
+      .any(|source| source == "git")
+    {
+      return Ok(None);
+    }
+    let mut git_dates = self.git_dates.borrow_mut();
+    let vault_dates = git_dates.entry(vault.src_dir.clone()).or_insert_with(|| {
+      commit_dates(&vault.src_dir).unwrap_or_else(|error| {
+        warn!(dir = %vault.src_dir.display(), error = %error, "no commit dates");
+        HashMap::new()
+      })
+    });
+    Ok(vault_dates.get(&fs::canonicalize(src)?).cloned())
+  }
+
+  /// The manifest post of `dst`, if it was exported from the same content of `src`
+  /// with the same settings and is still there
+  fn unchanged_post(&self, src: &Path, dst: &Path, hash: &str) -> Option<ManifestPost> {
+    let manifest = self.manifest.borrow();
+    let post = manifest.posts.get(dst)?;
+    let unchanged =
+      manifest.config == self.config_hash && post.src == src && post.hash == hash && dst.is_file();
+    unchanged.then(|| post.clone())
+  }
+
+  /// Export the note renamed from `from` to `to`, deleting the post at its old url
+  pub fn rename_note(&self, from: (&Vault, &Path), to: (&Vault, &Path)) -> Result<()> {
+    let old_dst = self.dst_path(from.0, from.1);
+    let new_dst = self.dst_path(to.0, to.1);
+    if self.rename_aliases && old_dst != new_dst {
+      let mut aliases = self.aliases.borrow_mut();
+      let mut old_aliases = aliases.get(&old_dst).cloned().unwrap_or_default();
+      if let Some(url) = self.post_path(&old_dst) {
+        old_aliases.push(url);
+      }
+      let new_aliases = aliases.entry(new_dst).or_default();
+      for alias in old_aliases {
+        if !new_aliases.contains(&alias) {
+          new_aliases.push(alias);
+        }
+      }
+    }
+
+    self.remove_note(from.0, from.1)?;
+    self.export_note(to.0, to.1)
+  }
+
+  /// Url of the post at `dst`, like `/blog/posts/my-note/` below the base path of the site
+  fn post_url(&self, dst: &Path) -> Option<String> {
+    Some(self.content_rules.slug.url(&self.post_path(dst)?))
+  }
+
+  /// Path of the post at `dst` in the site, like `/posts/my-note/`, as Hugo `aliases` are written
+  fn post_path(&self, dst: &Path) -> Option<String> {
+    let path = dst
+      .strip_prefix(self.hugo_dir.join("content"))
+      .ok()?
+      .with_extension("");
+    Some(format!("/{}/", path.to_str()?))
+  }
+
+  /// Urls of the Obsidian aliases of a note, the other names it is linked by, next to its post
+  fn note_aliases(&self, src_props: &BTreeMap<String, Prop>, dst: &Path) -> Vec<String> {
+    let Some(names) = src_props.get("aliases").map(Prop::strings) else {
+      return vec![];
+    };
+    let post_path = self.post_path(dst);
+    let mut aliases = vec![];
+    for name in names {
+      let url = self
+        .post_path(&dst.with_file_name(format!("{}.md", self.content_rules.slug.to_url(&name))));
+      if let Some(url) = url.filter(|url| Some(url) != post_path.as_ref()) {
+        if !aliases.contains(&url) {
+          aliases.push(url);
+        }
+      }
+    }
+    aliases
+  }
+
+  /// Aliases of the post at `dst`, those added by renames and those of the existing post
+  fn post_aliases(&self, dst: &Path) -> Result<Vec<String>> {
+    let mut aliases = self.aliases.borrow_mut();
+    let post_aliases = aliases.entry(dst.to_path_buf()).or_default();
+    if dst.is_file() {
+      let existing = read_src_props(dst)?
+        .and_then(|mut props| props.remove("aliases"))
+        .map(|aliases| aliases.strings())
+        .unwrap_or_default();
+      for alias in existing {
+        if !post_aliases.contains(&alias) {
+          post_aliases.push(alias);
+        }
+      }
+    }
+    Ok(post_aliases.clone())
+  }
+
+  /// Delete the post of a deleted note and the assets only it referenced
+  pub fn remove_note(&self, vault: &Vault, sub_path: &Path) -> Result<()> {
+    self.remove_post(
+      &vault.src_dir.join(sub_path),
+      &self.dst_path(vault, sub_path),
+    )
+  }
+
+  /// Delete the post of a note that is not published anymore, unless another note maps to it,
+  /// and the assets no other post references
+  fn remove_post(&self, src: &Path, dst: &Path) -> Result<()> {
+    let mut exported = self.exported.borrow_mut();
+    if exported.get(dst).is_some_and(|other| other != src) {
+      return Ok(());
+    }
+    exported.remove(dst);
+    self.remove_file(src, dst)?;
+
+    let mut manifest = self.manifest.borrow_mut();
+    let assets = manifest.posts.remove(dst).map(|post| post.assets);
+    for asset in assets.unwrap_or_default() {
+      if !manifest.is_asset_used(&asset.dst) {
+        self.remove_file(src, &asset.dst)?;
+      }
+    }
+    Ok(())
+  }
+
+  /// Delete a dst file of the note `src`, if it is in a dir owned by tecexp
+  fn remove_file(&self, src: &Path, dst: &Path) -> Result<()> {
+    if dst.is_file() && dst.parent().is_some_and(is_owned) {
+      info!(src = %src.display(), dst = %dst.display(), "delete");
+      self.stats.borrow_mut().deleted += 1;
+      if !self.options.dry_run {
+        self.delete(dst)?;
+      }
+    }
+    Ok(())
+  }
+
+  /// Delete a dst file or dir, moving it to the trash if enabled
+  fn delete(&self, path: &Path) -> Result<()> {
+    if self.trash {
+      trash::delete(path).with_context(|| format!("Cannot move {path:?} to the trash"))
+    } else if path.is_dir() {
+      Ok(fs::remove_dir_all(path)?)
+    } else {
+      Ok(fs::remove_file(path)?)
+    }
+  }
+
+  /// Move the dst file about to be overwritten to the trash, if enabled
+  fn trash_old(&self, path: &Path) -> Result<()> {
+    if self.trash && path.exists() {
+      self.delete(path)?;
+    }
+    Ok(())
+  }
+
+  /// Copy an asset referenced by a note, unless its dst is up to date
+  fn copy_asset(&self, src: &Path, dst: &Path) -> Result<()> {
+    if !self.options.force && !is_modified(src, dst)? {
+      return Ok(());
+    }
+
+    info!(src = %src.display(), dst = %dst.display(), "copy");
+    self.stats.borrow_mut().copied += 1;
+    if let Some(progress) = self.progress.borrow().as_ref() {
+      progress.copied();
+    }
+    if !self.options.dry_run {
+      self.trash_old(dst)?;
+      fs::copy(src, dst).with_context(|| format!("Cannot copy asset {src:?}"))?;
+      set_modified(dst, fs::metadata(src)?.modified()?)?;
+    }
+    Ok(())
+  }
+
+  fn skip(&self, src: &Path, reason: &str) {
+    debug!(src = %src.display(), reason, "skip");
+    if let Some(progress) = self.progress.borrow().as_ref() {
+      progress.skipped();
+    }
+  }
+}
+
+/// Whether `sub_path` is in a hidden dir like `.obsidian` or `.trash` or a hidden file itself,
+/// which the vault scan skips
+pub fn is_hidden(sub_path: &Path) -> bool {
+  sub_path
+    .components()
+    .any(|part| part.as_os_str().as_encoded_bytes().starts_with(b"."))
+}
+
+pub fn recursive_scan(
+  base_dir: &Path,
+  sub_dir: &Path,
+  filter: &Filter,
+  cb: &mut dyn FnMut(&Path) -> Result<()>,
+) -> Result<()> {
+  let dir = base_dir.join(sub_dir);
+
+  if dir.is_dir() {
+    for res in fs::read_dir(&dir)? {
+      let entry = res?;
+      let path = entry.path();
+      let name = entry.file_name();
+      if name.as_encoded_bytes()[0] == b'.' || name == DEFAULTS_FILE {
+        continue;
+      }
+      let sub_path = sub_dir.join(name);
+      if path.is_dir() {
+        if filter.scan_dir(&sub_path) {
+          recursive_scan(base_dir, &sub_path, filter, cb)?;
+        }
+      } else if Some(OsStr::new("md")) == path.extension() && filter.accept(&sub_path) {
+        trace!(sub_path = %sub_path.display(), "scan");
+        cb(&sub_path)?;
+      }
+    }
+  }
+  Ok(())
+}
+
+/// Ask on the terminal before deleting non-empty `dirs`
+fn confirm_delete(dirs: &[&PathBuf]) -> Result<()> {
+  let mut non_empty = vec![];
+  for dir in dirs {
+    let count = fs::read_dir(dir)?.count();
+    if count > 0 {
+      non_empty.push((dir, count));
+    }
+  }
+  if non_empty.is_empty() {
+    return Ok(());
+  }
+
+  eprintln!("The following dirs will be deleted:");
+  for (dir, count) in &non_empty {
+    eprintln!("  {} ({count} entries)", dir.display());
+  }
+  if !io::stdin().is_terminal() {
+    bail!("Refusing to delete dirs without confirmation, pass --yes to delete them");
+  }
+  eprint!("Delete them? [y/N] ");
+  let mut answer = String::new();
+  io::stdin().read_line(&mut answer)?;
+  if !matches!(answer.trim().to_lowercase().as_str(), "y" | "yes") {
+    bail!("Aborted, nothing is deleted");
+  }
+  Ok(())
+}
+
+/// Hash of a note and the notes it embeds
+fn note_hash(note: &[u8], embeds: &[PathBuf]) -> String {
+  let mut bytes = note.to_vec();
+  for embed in embeds {
+    bytes.extend(fs::read(embed).unwrap_or_default());
+  }
+  hash(&bytes)
+}
+
+/// Title of the note at `path`, its name
+fn note_title(path: &Path) -> Option<String> {
+  Some(path.file_stem()?.to_str()?.to_string())
+}
+
+/// Whether `dir` has the marker of the dirs created by tecexp
+fn is_owned(dir: &Path) -> bool {
+  dir.join(MARKER_FILE).is_file()
+}
+
+/// Give the dst file the mtime of its src, as Hugo's `:fileModTime` and `is_modified` expect
+fn set_modified(dst: &Path, modified: SystemTime) -> Result<()> {
+  File::options()
+    .write(true)
+    .open(dst)?
+    .set_modified(modified)?;
+  Ok(())
+}
+
+fn is_modified(src: &Path, dst: &Path) -> Result<bool> {
+  if !dst.exists() {
+    Ok(true)
+  } else {
+    let src_modified = fs::metadata(src)
+      .with_context(|| format!("Cannot find asset {src:?}"))?
+      .modified()?;
+    let dst_modified = fs::metadata(dst)?.modified()?;
+    Ok(src_modified.gt(&dst_modified))
+  }
+}
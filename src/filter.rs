@@ -0,0 +1,58 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+
+/// Include/exclude glob patterns, matched against paths relative to the vault dir
+pub struct Filter {
+  include: Option<GlobSet>,
+  exclude: GlobSet,
+}
+
+impl Filter {
+  pub fn new(include: &[String], exclude: &[String]) -> Result<Self> {
+    Ok(Self {
+      include: if include.is_empty() {
+        None
+      } else {
+        Some(build_glob_set(include)?)
+      },
+      exclude: build_glob_set(exclude)?,
+    })
+  }
+
+  /// Whether the dir at `sub_path` should be scanned
+  pub fn scan_dir(&self, sub_path: &Path) -> bool {
+    !self.exclude.is_match(sub_path)
+  }
+
+  /// Whether the note at `sub_path` should be exported, checking its parent dirs too
+  pub fn accept(&self, sub_path: &Path) -> bool {
+    if sub_path
+      .ancestors()
+      .any(|path| !path.as_os_str().is_empty() && self.exclude.is_match(path))
+    {
+      return false;
+    }
+    match &self.include {
+      Some(include) => include.is_match(sub_path),
+      None => true,
+    }
+  }
+}
+
+fn build_glob_set(patterns: &[String]) -> Result<GlobSet> {
+  let mut builder = GlobSetBuilder::new();
+  for pattern in patterns {
+    // `Templates/` stands for the dir and everything beneath it
+    let pattern = pattern.trim_end_matches('/');
+    for pattern in [pattern.to_string(), format!("{pattern}/**")] {
+      let glob = GlobBuilder::new(&pattern)
+        .literal_separator(true)
+        .build()
+        .with_context(|| format!("Invalid glob pattern `{pattern}`"))?;
+      builder.add(glob);
+    }
+  }
+  Ok(builder.build()?)
+}
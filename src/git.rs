@@ -0,0 +1,61 @@
+use std::{
+  collections::HashMap,
+  fs,
+  path::{Path, PathBuf},
+  process::Command,
+};
+
+use anyhow::{bail, Context, Result};
+
+/// Dates of the first and last commits of a file, as ISO 8601 strings
+#[derive(Debug, Clone)]
+pub struct GitDates {
+  pub first: String,
+  pub last: String,
+}
+
+/// Author dates of the files committed in the git repository of `dir`, by full path
+pub fn commit_dates(dir: &Path) -> Result<HashMap<PathBuf, GitDates>> {
+  let root = git(dir, &["rev-parse", "--show-toplevel"])?;
+  let root = fs::canonicalize(root.trim())?;
+
+  // A single log of the whole history, newest commits first
+  let log = git(
+    dir,
+    &["log", "--format=%x00%aI", "--name-only", "--no-renames"],
+  )?;
+  let mut dates: HashMap<PathBuf, GitDates> = HashMap::new();
+  let mut date = "";
+  for line in log.lines() {
+    if let Some(commit_date) = line.strip_prefix('\0') {
+      date = commit_date;
+    } else if !line.is_empty() {
+      dates
+        .entry(root.join(line))
+        .and_modify(|dates| dates.first = date.to_string())
+        .or_insert_with(|| GitDates {
+          first: date.to_string(),
+          last: date.to_string(),
+        });
+    }
+  }
+  Ok(dates)
+}
+
+fn git(dir: &Path, args: &[&str]) -> Result<String> {
+  let output = Command::new("git")
+    .arg("-C")
+    .arg(dir)
+    .args(["-c", "core.quotePath=false"])
+    .args(args)
+    .output()
+    .context("Cannot run git")?;
+  if !output.status.success() {
+    bail!(
+      "git {} failed in {dir:?}: {}",
+      args.join(" "),
+      String::from_utf8_lossy(&output.stderr).trim()
+    );
+  }
+  Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
@@ -0,0 +1,24 @@
+use serde::Serialize;
+
+/// Published notes and the links between them, written into the Hugo data dir
+/// for themes to draw a graph like Obsidian's
+#[derive(Debug, Default, Serialize)]
+pub struct Graph {
+  pub nodes: Vec<GraphNode>,
+  pub edges: Vec<GraphEdge>,
+}
+
+/// Post of a published note, identified by its url
+#[derive(Debug, Serialize)]
+pub struct GraphNode {
+  pub id: String,
+  pub title: String,
+  pub url: String,
+}
+
+/// Link or embed from the post `source` to the post `target`
+#[derive(Debug, Serialize)]
+pub struct GraphEdge {
+  pub source: String,
+  pub target: String,
+}
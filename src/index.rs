@@ -0,0 +1,73 @@
+use std::{
+  collections::HashMap,
+  path::{Path, PathBuf},
+};
+
+/// Notes of the vaults by name, to resolve `[[links]]` the way Obsidian does,
+/// ignoring the case and the spacing of names
+#[derive(Debug, Default)]
+pub struct NoteIndex {
+  /// Notes by normalized file name without `.md`
+  by_name: HashMap<String, Vec<IndexedNote>>,
+}
+
+#[derive(Debug)]
+struct IndexedNote {
+  full_path: PathBuf,
+  /// Path in the vault without `.md`, like `Projects/Note`
+  link_path: PathBuf,
+  /// Normalized names of the folders and the note of `link_path`
+  names: Vec<String>,
+}
+
+impl NoteIndex {
+  /// Add the note at `sub_path` of a vault
+  pub fn insert(&mut self, sub_path: &Path, full_path: PathBuf) {
+    let link_path = sub_path.with_extension("");
+    let names = normalize_path(&link_path);
+    let Some(name) = names.last() else {
+      return;
+    };
+    self
+      .by_name
+      .entry(name.clone())
+      .or_default()
+      .push(IndexedNote {
+        full_path,
+        link_path,
+        names,
+      });
+  }
+
+  /// Full path of the note `[[link]]` points to from the note at `from`.
+  /// `link` is a name like `Note` or a path like `Projects/Note`,
+  /// notes of the same name resolving to the one written the same, then the one in the folder
+  /// of `from`, else the shortest path.
+  pub fn resolve(&self, link: &str, from: Option<&Path>) -> Option<&Path> {
+    let link = link.trim();
+    let link = Path::new(link.strip_suffix(".md").unwrap_or(link));
+    let names = normalize_path(link);
+    self
+      .by_name
+      .get(names.last()?)?
+      .iter()
+      .filter(|note| note.names.ends_with(&names))
+      .min_by_key(|note| {
+        let same_case = note.link_path.ends_with(link);
+        let same_folder = from.is_some_and(|from| note.full_path.parent() == from.parent());
+        (!same_case, !same_folder, note.names.len(), &note.link_path)
+      })
+      .map(|note| note.full_path.as_path())
+  }
+}
+
+/// The names of the components of `path` in lower case, with single spaces between words
+fn normalize_path(path: &Path) -> Vec<String> {
+  path
+    .iter()
+    .map(|name| {
+      let name = name.to_string_lossy().to_lowercase();
+      name.split_whitespace().collect::<Vec<_>>().join(" ")
+    })
+    .collect()
+}
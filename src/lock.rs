@@ -0,0 +1,100 @@
+use std::{
+  fs::{self, OpenOptions},
+  io::{ErrorKind, Write},
+  path::{Path, PathBuf},
+  process, thread,
+  time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use tracing::{info, warn};
+
+/// Lock file in the Hugo dir, held by the run writing into it
+const LOCK_FILE: &str = ".tecexp.lock";
+
+/// What to do when another run holds the lock
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LockOptions {
+  /// Wait until the other run releases the lock
+  pub wait: bool,
+  /// Take the lock over, e.g. when the other run crashed
+  pub steal: bool,
+}
+
+/// Lock of a Hugo dir, released on drop
+pub struct Lock {
+  path: PathBuf,
+}
+
+impl Lock {
+  pub fn acquire(hugo_dir: &Path, options: LockOptions) -> Result<Self> {
+    let path = hugo_dir.join(LOCK_FILE);
+    let mut waiting = false;
+    loop {
+      match OpenOptions::new().write(true).create_new(true).open(&path) {
+        Ok(mut file) => {
+          writeln!(file, "{}", process::id())?;
+          return Ok(Self { path });
+        }
+        Err(err) if err.kind() == ErrorKind::AlreadyExists => {}
+        Err(err) => return Err(err).with_context(|| format!("Cannot create lock {path:?}")),
+      }
+
+      let pid = fs::read_to_string(&path).unwrap_or_default();
+      let pid = pid.trim();
+      if options.steal || !is_running(pid) {
+        warn!(lock = %path.display(), pid, "steal lock");
+        fs::remove_file(&path)?;
+      } else if options.wait {
+        if !waiting {
+          info!(lock = %path.display(), pid, "wait for lock");
+          waiting = true;
+        }
+        thread::sleep(Duration::from_millis(200));
+      } else {
+        bail!(
+          "{hugo_dir:?} is locked by another tecexp run (pid {pid}), \
+          pass --wait to wait for it or --steal-lock to take the lock over"
+        );
+      }
+    }
+  }
+}
+
+/// Whether the process `pid` still runs, not if `pid` is no PID, assumed when it can't be told
+pub fn is_running(pid: &str) -> bool {
+  let Ok(pid) = pid.parse::<i32>() else {
+    return false;
+  };
+  #[cfg(unix)]
+  {
+    use nix::{errno::Errno, sys::signal::kill, unistd::Pid};
+    // Signal 0 only checks the process, which runs as another user if it may not be signaled
+    pid > 0 && matches!(kill(Pid::from_raw(pid), None), Ok(()) | Err(Errno::EPERM))
+  }
+  #[cfg(not(unix))]
+  {
+    pid > 0
+  }
+}
+
+impl Drop for Lock {
+  fn drop(&mut self) {
+    let _ = fs::remove_file(&self.path);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn is_running_tells_pids() {
+    assert!(is_running(&process::id().to_string()));
+    assert!(!is_running(""));
+    assert!(!is_running("12ab"));
+    assert!(!is_running("0"));
+    // Above the default maximum PID of Linux and macOS
+    assert!(!is_running("99999999"));
+  }
+}
@@ -1,385 +1,648 @@
 use std::{
-  collections::BTreeMap,
-  ffi::OsStr,
-  fs::{self, File},
-  io::{BufRead, BufReader, BufWriter, Lines, Write},
-  iter::{Flatten, Peekable},
-  path::Path,
-  sync::mpsc::channel,
+  io::{self, IsTerminal, Write},
+  path::{Path, PathBuf},
+  process,
+  time::{Duration, SystemTime},
 };
 
-use anyhow::Result;
-use clap::Parser;
-use notify::{Config, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
-use time::{format_description::well_known::Iso8601, OffsetDateTime};
-
-#[derive(Debug, Clone)]
-enum Prop {
-  Str(String),
-  Vec(Vec<String>),
-}
+use anyhow::{bail, Context, Result};
+use clap::{
+  builder::PossibleValuesParser, ArgAction, Args, CommandFactory, Parser, Subcommand, ValueEnum,
+};
+use clap_complete::Shell;
+use config::Config;
+use convert::{
+  build_dst_props, default_link_url, public_lines, write_content, write_props, ContentRules,
+  NoteDates,
+};
+use daemon::PidFiles;
+use export::{recursive_scan, Exporter, RunOptions};
+use lock::{Lock, LockOptions};
+use progress::LogWriter;
+use props::{extract_src_props, read_src_props, FrontMatterFormat, PropRules};
+use publish::PublishBy;
+use template::TemplateVars;
+use time::OffsetDateTime;
+use tracing::{error, info, warn, Level};
+use watch::WatchOptions;
+
+mod backup;
+mod config;
+mod convert;
+mod daemon;
+mod date;
+mod export;
+mod filter;
+mod git;
+mod graph;
+mod index;
+mod lock;
+mod manifest;
+mod progress;
+mod props;
+mod publish;
+mod slug;
+mod template;
+mod watch;
+mod webhook;
 
 /// Export mds from Obsidian to Hugo
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
-struct Args {
-  /// Obsidian vault dir
-  #[arg(short, long)]
-  obsidian_dir: String,
+struct Cli {
+  #[command(flatten)]
+  args: GlobalArgs,
 
-  /// Hugo dir
-  #[arg(short('g'), long)]
-  hugo_dir: String,
+  #[command(subcommand)]
+  command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+  /// Export published notes to Hugo
+  Export(ExportArgs),
 
-  /// Hugo posts sub dir
-  #[arg(short('p'), long, default_value = "content/posts")]
-  hugo_posts_dir: String,
+  /// Export published notes, then re-export them whenever they are modified, renamed or deleted
+  Watch(WatchArgs),
 
-  /// Hugo assets sub dir
-  #[arg(short('a'), long, default_value = "content/assets")]
-  hugo_assets_dir: String,
+  /// Watch in a managed process, with a PID file for `status` and `stop`
+  Daemon(DaemonArgs),
 
-  /// Watch
-  #[arg(short, long, default_value_t = false)]
-  watch: bool,
+  /// Tell whether the daemon is running
+  Status(PidFileArgs),
+
+  /// Stop the daemon, letting it finish its exports
+  Stop(PidFileArgs),
+
+  /// Remove the Hugo posts and assets dirs
+  Clean(CleanArgs),
+
+  /// Check the settings and list the notes that would be published
+  Check,
+
+  /// Convert a single note read from stdin and write it to stdout
+  Pipe(PipeArgs),
+
+  /// Print the shell completion script, including the profiles of the config file
+  Completions(CompletionsArgs),
 }
 
-fn main() -> Result<()> {
-  let args = Args::parse();
+#[derive(Args, Debug)]
+struct ExportArgs {
+  /// Report what would be written, copied and deleted without touching any file
+  #[arg(short('n'), long, env = "TECEXP_DRY_RUN")]
+  dry_run: bool,
 
-  let obsidian_dir = fs::canonicalize(args.obsidian_dir).expect("Cannot find Obsidian vault dir");
-  let hugo_dir = fs::canonicalize(args.hugo_dir).expect("Cannot find hugo dir");
+  /// Only export this note, relative to the vault dir (can be repeated)
+  #[arg(short, long)]
+  file: Vec<PathBuf>,
 
-  let src_dir = obsidian_dir;
-  let asset_src = src_dir.join("assets");
+  /// Only export notes modified after a timestamp or a duration ago (e.g. 12h), keeping other posts
+  #[arg(short, long, value_parser = date::parse_since, env = "TECEXP_SINCE")]
+  since: Option<SystemTime>,
 
-  let dst_dir = hugo_dir.join(args.hugo_posts_dir);
-  let asset_dst = hugo_dir.join(args.hugo_assets_dir);
+  /// Export every note and copy every asset, even if the post is up to date
+  #[arg(long, conflicts_with = "since", env = "TECEXP_FORCE")]
+  force: bool,
 
-  if dst_dir.exists() {
-    fs::remove_dir_all(&dst_dir)?;
-  }
-  fs::create_dir(&dst_dir)?;
-  if asset_dst.exists() {
-    fs::remove_dir_all(&asset_dst)?;
-  }
-  fs::create_dir(&asset_dst)?;
-
-  recursive_scan(&src_dir, Path::new(""), &|sub_path| {
-    export(
-      &src_dir.join(sub_path),
-      &dst_dir.join(to_url(sub_path.to_str().unwrap())),
-      &asset_src,
-      &asset_dst,
-    )
-  })?;
-
-  if !args.watch {
-    return Ok(());
-  }
+  /// Draw a progress bar of the processed notes
+  #[arg(long, env = "TECEXP_PROGRESS")]
+  progress: bool,
 
-  println!("=== \n Watch {src_dir:?} \n===");
-
-  let (tx, rx) = channel();
-  let mut watcher = RecommendedWatcher::new(tx, Config::default())?;
-  watcher.watch(&src_dir, RecursiveMode::Recursive)?;
-
-  for res in rx {
-    match res {
-      Ok(event) => match event.kind {
-        EventKind::Modify(_) => {
-          for full_path in &event.paths {
-            let file_name = full_path.file_name().unwrap().to_str().unwrap();
-            if file_name.starts_with('.') || file_name.ends_with('~') {
-              continue;
-            }
-            if let Ok(sub_path) = full_path.strip_prefix(&src_dir) {
-              export(
-                &full_path,
-                &dst_dir.join(to_url(sub_path.to_str().unwrap())),
-                &asset_src,
-                &asset_dst,
-              )?;
-            }
-          }
-        }
-        _ => {}
-      },
-      Err(error) => println!("Error: {error:?}"),
+  /// Fail if a post links to or embeds a note found in no vault
+  #[arg(long, env = "TECEXP_STRICT_LINKS")]
+  strict_links: bool,
+}
+
+impl ExportArgs {
+  fn options(&self, yes: bool) -> RunOptions {
+    RunOptions {
+      yes,
+      dry_run: self.dry_run,
+      since: self.since,
+      force: self.force,
+      progress: self.progress,
     }
   }
-
-  Ok(())
 }
 
-fn recursive_scan(base_dir: &Path, sub_dir: &Path, cb: &dyn Fn(&Path) -> Result<()>) -> Result<()> {
-  let dir = base_dir.join(sub_dir);
+#[derive(Args, Debug)]
+struct WatchArgs {
+  /// Wait for this many milliseconds without changes before exporting the changed notes
+  #[arg(
+    long,
+    value_name = "MS",
+    default_value_t = 200,
+    env = "TECEXP_DEBOUNCE"
+  )]
+  debounce: u64,
+
+  /// Also rewrite the posts embedding a modified asset, for Hugo to reload their pages
+  #[arg(long, env = "TECEXP_EXPORT_ASSET_NOTES")]
+  export_asset_notes: bool,
+
+  /// Scan the vaults every this many milliseconds instead of listening to OS events,
+  /// for vaults on NFS, SMB or WSL mounts
+  #[arg(long, value_name = "MS", env = "TECEXP_POLL_INTERVAL")]
+  poll_interval: Option<u64>,
+
+  /// Shell command to run after each batch of exports, e.g. `hugo --gc`
+  #[arg(long, value_name = "COMMAND", env = "TECEXP_ON_EXPORT")]
+  on_export: Option<String>,
+
+  /// Listen on this address, e.g. 127.0.0.1:8090, for POST requests triggering a full export
+  #[arg(long, value_name = "ADDR", env = "TECEXP_WEBHOOK")]
+  webhook: Option<String>,
+
+  /// Bearer token the webhook requests must send
+  #[arg(
+    long,
+    value_name = "TOKEN",
+    requires = "webhook",
+    env = "TECEXP_WEBHOOK_TOKEN"
+  )]
+  webhook_token: Option<String>,
+}
 
-  if dir.is_dir() {
-    for res in fs::read_dir(&dir)? {
-      let entry = res?;
-      let path = entry.path();
-      let name = entry.file_name();
-      if name.as_encoded_bytes()[0] == b'.' {
-        continue;
-      }
-      let sub_path = sub_dir.join(name);
-      if path.is_dir() {
-        recursive_scan(base_dir, &sub_path, cb)?;
-      } else {
-        if Some(OsStr::new("md")) == path.extension() {
-          // println!("{sub_path:?}");
-          cb(&sub_path)?;
-        }
-      }
+impl WatchArgs {
+  fn options(&self) -> WatchOptions {
+    WatchOptions {
+      debounce: Duration::from_millis(self.debounce),
+      export_asset_notes: self.export_asset_notes,
+      poll_interval: self.poll_interval.map(Duration::from_millis),
+      on_export: self.on_export.clone(),
+      webhook: self.webhook.clone(),
+      webhook_token: self.webhook_token.clone(),
     }
   }
-  Ok(())
 }
 
-// fn to_hex_path(path: &Path) -> String {
-//   let bytes = path.as_os_str().as_encoded_bytes();
-//   let mut p = bytes[..bytes.len() - 3]
-//     .iter()
-//     .map(|b| format!("{:02x}", b))
-//     .collect::<Vec<String>>()
-//     .join("");
-//   p.push_str(".md");
-//   p
-// }
-
-fn to_url(p: &str) -> String {
-  p.replace(" ", "-").replace("/", "-").to_lowercase()
-  // let p = path
-  //   .to_str()
-  //   .unwrap()
-  //   .replace(" ", "-")
-  //   .replace("/", "-")
-  //   .to_lowercase();
-  // p
-  // form_urlencoded::byte_serialize(p.as_bytes()).collect()
-  // let bytes: Vec<u8> = path
-  //   .as_os_str()
-  //   .to_ascii_lowercase()
-  //   .as_encoded_bytes()
-  //   .iter()
-  //   .map(|b| if b.is_ascii_whitespace() { b'-' } else { *b })
-  //   .collect();
-  // String::from_utf8_lossy(&bytes).to_string()
+#[derive(Args, Debug)]
+struct DaemonArgs {
+  #[command(flatten)]
+  watch: WatchArgs,
+
+  #[command(flatten)]
+  pid_file: PidFileArgs,
+
+  /// Run in the background, logging to the log file
+  #[arg(long, env = "TECEXP_DETACH")]
+  detach: bool,
+
+  /// Log file of the detached daemon [default: .tecexp.log in the Hugo dir]
+  #[arg(long, requires = "detach", env = "TECEXP_LOG_FILE")]
+  log_file: Option<PathBuf>,
 }
 
-fn export(src: &Path, dst: &Path, asset_src: &Path, asset_dst: &Path) -> Result<()> {
-  let src_file = File::open(src)?;
-  let mut src_lines = BufReader::new(src_file).lines().flatten().peekable();
+#[derive(Args, Debug)]
+struct PidFileArgs {
+  /// PID file of the daemon [default: .tecexp.pid in the Hugo dirs]
+  #[arg(long, env = "TECEXP_PID_FILE")]
+  pid_file: Option<PathBuf>,
+}
 
-  // Extract src props
-  if let Some(src_props) = extract_src_props(&mut src_lines) {
-    if !contain_publish_web(&src_props) {
-      return Ok(());
-    }
+#[derive(Args, Debug)]
+struct CleanArgs {
+  /// Report what would be deleted without touching any file
+  #[arg(short('n'), long, env = "TECEXP_DRY_RUN")]
+  dry_run: bool,
+}
 
-    if !is_modified(src, dst) {
-      return Ok(());
-    }
+#[derive(Args, Debug)]
+struct PipeArgs {
+  /// Title of the note, usually its file name without `.md`
+  #[arg(long)]
+  title: Option<String>,
+}
 
-    println!("\n export: {src:?} \n    -> {dst:?}");
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum LogFormat {
+  /// Human readable lines
+  Text,
+  /// A JSON object per line, e.g. per export, copy, skip or error
+  Json,
+}
 
-    // Build dst props
-    let dst_props = build_dst_props(&src_props, src);
+#[derive(Args, Debug)]
+struct CompletionsArgs {
+  /// Shell to complete in
+  shell: Shell,
+}
 
-    let dst_file = File::create(dst)?;
-    let mut writer = BufWriter::new(dst_file);
+/// Options shared by all commands
+#[derive(Args, Debug, Clone)]
+struct GlobalArgs {
+  /// Log skipped notes too, -vv logs every scanned file
+  #[arg(short, long, global = true, action = ArgAction::Count, conflicts_with = "quiet")]
+  verbose: u8,
+
+  /// Only log warnings and errors
+  #[arg(short, long, global = true, env = "TECEXP_QUIET")]
+  quiet: bool,
+
+  /// Delete the Hugo posts and assets dirs without asking for confirmation
+  #[arg(short, long, global = true, env = "TECEXP_YES")]
+  yes: bool,
+
+  /// Wait for another run writing into the Hugo dir to finish
+  #[arg(
+    long,
+    global = true,
+    conflicts_with = "steal_lock",
+    env = "TECEXP_WAIT"
+  )]
+  wait: bool,
+
+  /// Take the lock of the Hugo dir over from another run
+  #[arg(long, global = true, env = "TECEXP_STEAL_LOCK")]
+  steal_lock: bool,
+
+  /// Format of the log lines on stderr
+  #[arg(long, global = true, value_enum, default_value_t = LogFormat::Text, env = "TECEXP_LOG_FORMAT")]
+  log_format: LogFormat,
+
+  /// Config file [default: tecexp.toml if present]
+  #[arg(short, long, global = true, env = "TECEXP_CONFIG")]
+  config: Option<PathBuf>,
+
+  /// Profile of the config file to run (can be repeated) [default: all profiles]
+  #[arg(short('P'), long, global = true, env = "TECEXP_PROFILE")]
+  profile: Vec<String>,
+
+  /// Obsidian vault dir (can be repeated to merge several vaults)
+  #[arg(short, long, global = true, env = "TECEXP_OBSIDIAN_DIR")]
+  obsidian_dir: Vec<PathBuf>,
 
-    // Write dst props
-    writeln!(writer, "---")?;
-    for (key, val) in dst_props.iter() {
-      match val {
-        Prop::Str(s) => {
-          writeln!(writer, "{key}: {s}")?;
-        }
-        Prop::Vec(v) => {
-          writeln!(writer, "{key}:")?;
-          for item in v {
-            writeln!(writer, " - {item}")?;
-          }
-        }
-      }
-    }
-    writeln!(writer, "---")?;
+  /// Hugo dir
+  #[arg(short('g'), long, global = true, env = "TECEXP_HUGO_DIR")]
+  hugo_dir: Option<PathBuf>,
 
-    // Write content
-    let mut is_coding = false;
-    for line in src_lines {
-      if line.trim().eq("=== end ===") {
-        break;
-      }
+  /// Hugo posts sub dir [default: content/posts]
+  #[arg(short('p'), long, global = true, env = "TECEXP_HUGO_POSTS_DIR")]
+  hugo_posts_dir: Option<PathBuf>,
 
-      // Ignore coding blocks
-      if !is_coding && line.trim().starts_with("```") {
-        is_coding = true;
-      }
+  /// Hugo assets sub dir [default: content/assets]
+  #[arg(short('a'), long, global = true, env = "TECEXP_HUGO_ASSETS_DIR")]
+  hugo_assets_dir: Option<PathBuf>,
 
-      if is_coding {
-        writeln!(writer, "{line}")?;
-        if line.trim().eq("```") {
-          is_coding = false;
-        }
-        continue;
-      }
+  /// Only export notes matching this glob, relative to the vault dir (can be repeated)
+  #[arg(short, long, global = true, env = "TECEXP_INCLUDE")]
+  include: Vec<String>,
 
-      // Write line by line
-      let mut curr = 0;
-      // Replace `[[Some title]]` to `[Some tile](/posts/some-title/)`
-      // Replace `[[some-img.png]]` to `[some-img.png](/assets/some-img.png)`
-      while let Some(start) = line[curr..].find("[[") {
-        write!(writer, "{}", &line[curr..(curr + start)])?;
-        curr += start;
-        if let Some(end) = line[(curr + 2)..].find("]]") {
-          let inner = &line[(curr + 2)..(curr + 2 + end)];
-          if inner.ends_with(".png") || inner.ends_with(".jpg") {
-            let inner_url = to_url(inner);
-            let img_src = asset_src.join(inner);
-            let img_dst = asset_dst.join(&inner_url);
-            println!("    copy: {img_src:?} \n      -> {img_dst:?}");
-            fs::copy(img_src, img_dst)?;
-            write!(writer, "[{inner_url}](/assets/{inner_url})")?;
-          } else if !inner.trim().is_empty() {
-            write!(writer, "[{}](/posts/{}/)", inner, to_url(inner))?;
-          } else {
-            write!(writer, "[[{inner}]]")?;
-          }
-          curr += 2 + end + 2;
+  /// Skip notes and dirs matching this glob, relative to the vault dir (can be repeated)
+  #[arg(short('x'), long, global = true, env = "TECEXP_EXCLUDE")]
+  exclude: Vec<String>,
+
+  /// Prop deciding whether a note is published [default: publish]
+  #[arg(short('k'), long, global = true, env = "TECEXP_PUBLISH_KEY")]
+  publish_key: Option<String>,
+
+  /// Accepted value of the publish prop (can be repeated) [default: web]
+  #[arg(short('u'), long, global = true, env = "TECEXP_PUBLISH_VALUE")]
+  publish_value: Vec<String>,
+
+  /// Value of the publish prop exporting notes as drafts (can be repeated) [default: draft]
+  #[arg(long, global = true, env = "TECEXP_PUBLISH_DRAFT")]
+  publish_draft: Vec<String>,
+
+  /// Only accept the publish values, not `publish: true`
+  #[arg(long, global = true, env = "TECEXP_PUBLISH_STRICT")]
+  publish_strict: bool,
+
+  /// Publish notes tagged with this tag (can be repeated)
+  #[arg(short('t'), long, global = true, env = "TECEXP_PUBLISH_TAG")]
+  publish_tag: Vec<String>,
+
+  /// Select notes to publish by [default: prop, either with --publish-tag]
+  #[arg(long, global = true, value_enum, env = "TECEXP_PUBLISH_BY")]
+  publish_by: Option<PublishBy>,
+
+  /// Keep the old url of notes renamed while watching as Hugo aliases of their post
+  #[arg(long, global = true, env = "TECEXP_RENAME_ALIASES")]
+  rename_aliases: bool,
+
+  /// Take the date and lastmod of posts from the first and last commits of their note
+  #[arg(long, global = true, env = "TECEXP_DATE_FROM_GIT")]
+  date_from_git: bool,
+
+  /// Demote the headings of notes by this many levels, for themes writing the title as `h1`
+  #[arg(long, global = true, env = "TECEXP_SHIFT_HEADINGS")]
+  shift_headings: Option<usize>,
+
+  /// Frontmatter format of the posts [default: yaml]
+  #[arg(long, global = true, value_enum, env = "TECEXP_FRONT_MATTER_FORMAT")]
+  front_matter_format: Option<FrontMatterFormat>,
+
+  /// Move deleted and overwritten posts and assets to the trash instead of removing them
+  #[arg(long, global = true, env = "TECEXP_TRASH")]
+  trash: bool,
+
+  /// Back the posts and assets dirs up into this dir before `clean` and `export --force`,
+  /// relative to the Hugo dir
+  #[arg(long, global = true, env = "TECEXP_BACKUP_DIR")]
+  backup_dir: Option<PathBuf>,
+
+  /// Number of backups to keep [default: 5]
+  #[arg(long, global = true, env = "TECEXP_BACKUP_KEEP")]
+  backup_keep: Option<usize>,
+}
+
+fn main() -> Result<()> {
+  let cli = Cli::parse();
+  init_logging(&cli.args);
+  let log_format = cli.args.log_format;
+
+  let res = run(cli);
+  if let (LogFormat::Json, Err(err)) = (log_format, &res) {
+    error!(error = format!("{err:#}"), "error");
+    process::exit(1);
+  }
+  res
+}
+
+fn run(cli: Cli) -> Result<()> {
+  if let Command::Completions(args) = &cli.command {
+    return completions(args.shell, &cli.args);
+  }
+
+  let yes = cli.args.yes;
+  let lock_options = LockOptions {
+    wait: cli.args.wait,
+    steal: cli.args.steal_lock,
+  };
+  let args = cli.args.clone();
+  let configs = Config::load(cli.args)?;
+
+  match cli.command {
+    Command::Export(args) => {
+      let exporters = exporters(&configs, args.options(yes))?;
+      let _locks = lock(&exporters, lock_options)?;
+      let mut broken_links = 0;
+      for exporter in &exporters {
+        print_profile(exporter);
+        if args.file.is_empty() {
+          exporter.export_all()?;
         } else {
-          write!(writer, "{}", &line[curr..])?;
-          curr = line.len();
+          exporter.export_files(&args.file)?;
         }
+        broken_links += exporter.report_broken_links()?;
+      }
+      if args.strict_links && broken_links > 0 {
+        bail!("{broken_links} broken links");
+      }
+      Ok(())
+    }
+    Command::Watch(watch_args) => {
+      let options = RunOptions {
+        yes,
+        ..RunOptions::default()
+      };
+      let exporters = exporters(&configs, options.clone())?;
+      let _locks = lock(&exporters, lock_options)?;
+      let locked = owned_hugo_dirs(&exporters);
+      watch::watch(exporters, &watch_args.options(), || {
+        reload(&args, &options, &locked)
+      })
+    }
+    Command::Daemon(daemon_args) => {
+      let options = RunOptions {
+        yes,
+        ..RunOptions::default()
+      };
+      let exporters = exporters(&configs, options.clone())?;
+      let locked = owned_hugo_dirs(&exporters);
+      if daemon_args.detach {
+        return daemon::detach(daemon_args.log_file.as_deref(), &locked[0]);
+      }
+      let _locks = lock(&exporters, lock_options)?;
+      let _pid_files = PidFiles::create(daemon::pid_paths(
+        daemon_args.pid_file.pid_file.as_deref(),
+        &hugo_dirs(&exporters),
+      ))?;
+      watch::watch(exporters, &daemon_args.watch.options(), || {
+        reload(&args, &options, &locked)
+      })
+    }
+    Command::Status(args) => {
+      let exporters = exporters(&configs, RunOptions::default())?;
+      daemon::status(&daemon::pid_paths(
+        args.pid_file.as_deref(),
+        &hugo_dirs(&exporters),
+      ))
+    }
+    Command::Stop(args) => {
+      let exporters = exporters(&configs, RunOptions::default())?;
+      daemon::stop(&daemon::pid_paths(
+        args.pid_file.as_deref(),
+        &hugo_dirs(&exporters),
+      ))
+    }
+    Command::Clean(args) => {
+      let options = RunOptions {
+        yes,
+        dry_run: args.dry_run,
+        ..RunOptions::default()
+      };
+      let exporters = exporters(&configs, options)?;
+      let _locks = lock(&exporters, lock_options)?;
+      for exporter in &exporters {
+        print_profile(exporter);
+        exporter.clean()?;
+      }
+      Ok(())
+    }
+    Command::Check => {
+      for exporter in exporters(&configs, RunOptions::default())? {
+        print_profile(&exporter);
+        check(&exporter)?;
       }
-      write!(writer, "{}\n", &line[curr..])?;
+      Ok(())
     }
-    writer.flush()?;
+    Command::Pipe(args) => pipe(args, &configs[0]),
+    Command::Completions(_) => Ok(()),
   }
-
-  Ok(())
 }
 
-fn build_dst_props(src_props: &BTreeMap<String, Prop>, src: &Path) -> BTreeMap<String, Prop> {
-  let mut props: BTreeMap<String, Prop> = BTreeMap::new();
-
-  let title = src
-    .file_name()
-    .unwrap()
-    .to_str()
-    .unwrap()
-    .trim_end_matches(".md");
-
-  props.insert("title".to_string(), Prop::Str(title.to_string()));
+fn init_logging(args: &GlobalArgs) {
+  let level = match (args.quiet, args.verbose) {
+    (true, _) => Level::WARN,
+    (false, 0) => Level::INFO,
+    (false, 1) => Level::DEBUG,
+    (false, _) => Level::TRACE,
+  };
+  let builder = tracing_subscriber::fmt()
+    .with_max_level(level)
+    .with_target(false)
+    .with_ansi(io::stderr().is_terminal())
+    .with_writer(|| LogWriter);
+  match args.log_format {
+    LogFormat::Text => builder.init(),
+    LogFormat::Json => builder.json().flatten_event(true).init(),
+  }
+}
 
-  let modified: OffsetDateTime = fs::metadata(src).unwrap().modified().unwrap().into();
-  props.insert(
-    "date".to_string(),
-    Prop::Str(modified.format(&Iso8601::DEFAULT).unwrap()),
-  );
+/// An exporter per selected profile
+fn exporters(configs: &[Config], options: RunOptions) -> Result<Vec<Exporter>> {
+  configs
+    .iter()
+    .map(|config| Exporter::new(config, options.clone()))
+    .collect()
+}
 
-  if let Some(tags) = src_props.get("tags") {
-    props.insert("tags".to_string(), tags.clone());
+/// Hugo dirs of the exporters, without duplicates
+fn hugo_dirs(exporters: &[Exporter]) -> Vec<&PathBuf> {
+  let mut hugo_dirs: Vec<&PathBuf> = vec![];
+  for exporter in exporters {
+    if !hugo_dirs.contains(&&exporter.hugo_dir) {
+      hugo_dirs.push(&exporter.hugo_dir);
+    }
   }
+  hugo_dirs
+}
 
-  props
+/// `hugo_dirs` outliving the exporters
+fn owned_hugo_dirs(exporters: &[Exporter]) -> Vec<PathBuf> {
+  hugo_dirs(exporters).into_iter().cloned().collect()
 }
 
-fn contain_publish_web(props: &BTreeMap<String, Prop>) -> bool {
-  if let Some(Prop::Str(v)) = props.get("publish") {
-    v.eq("web")
-  } else {
-    false
+/// Load the config file again and build the exporters, which must write into the `locked` Hugo dirs
+fn reload(args: &GlobalArgs, options: &RunOptions, locked: &[PathBuf]) -> Result<Vec<Exporter>> {
+  let exporters = exporters(&Config::load(args.clone())?, options.clone())?;
+  if owned_hugo_dirs(&exporters) != locked {
+    bail!("The Hugo dirs changed, restart to export into them");
   }
+  Ok(exporters)
 }
 
-fn is_modified(src: &Path, dst: &Path) -> bool {
-  if !dst.exists() {
-    true
-  } else {
-    let src_modified = fs::metadata(src).unwrap().modified().unwrap();
-    let dst_modified = fs::metadata(dst).unwrap().modified().unwrap();
-    src_modified.gt(&dst_modified)
+/// Lock the Hugo dirs of the exporters, unless they only report what they would do
+fn lock(exporters: &[Exporter], options: LockOptions) -> Result<Vec<Lock>> {
+  if exporters.iter().all(|exporter| exporter.options.dry_run) {
+    return Ok(vec![]);
   }
+  hugo_dirs(exporters)
+    .into_iter()
+    .map(|hugo_dir| Lock::acquire(hugo_dir, options))
+    .collect()
 }
 
-fn extract_src_props(
-  lines: &mut Peekable<Flatten<Lines<BufReader<File>>>>,
-) -> Option<BTreeMap<String, Prop>> {
-  while let Some(line) = lines.peek() {
-    if line.is_empty() {
-      lines.next();
-    } else {
-      break;
-    }
+fn print_profile(exporter: &Exporter) {
+  if let Some(name) = &exporter.profile {
+    info!(profile = name, "profile");
   }
-  if let Some(line) = lines.peek() {
-    if line.trim().eq("---") {
-      lines.next();
-    } else {
-      return None;
-    }
-  }
-
-  let mut props: BTreeMap<String, Prop> = BTreeMap::new();
-  let mut vec_key = String::new();
+}
 
-  while let Some(line) = lines.next() {
-    if line.trim().eq("---") {
-      break;
+fn check(exporter: &Exporter) -> Result<()> {
+  let mut total = 0;
+  let mut published = 0;
+  for vault in &exporter.vaults {
+    println!("vault:  {:?}", vault.src_dir);
+    println!("posts:  {:?}", vault.dst_dir);
+    if !vault.asset_src.is_dir() {
+      warn!(dir = %vault.asset_src.display(), "no assets dir in vault");
     }
-    // println!("> {line}");
-    if let Some(pos) = line.find(':') {
-      let key = line[..pos].trim();
-      let val = line[(pos + 1)..].trim();
-      if !key.is_empty() && !val.is_empty() {
-        if let Some(vec) = str_to_vec(val) {
-          props.insert(key.to_string(), Prop::Vec(vec));
-        } else {
-          props.insert(key.to_string(), Prop::Str(val.to_string()));
+
+    recursive_scan(
+      &vault.src_dir,
+      Path::new(""),
+      &exporter.filter,
+      &mut |sub_path| {
+        total += 1;
+        match read_src_props(&vault.src_dir.join(sub_path)) {
+          Ok(Some(props)) if exporter.publish.is_published(&props) => {
+            published += 1;
+            println!(" publish: {sub_path:?}");
+          }
+          Ok(_) => {}
+          Err(error) => warn!(note = ?sub_path, error = %error.root_cause(), "invalid frontmatter"),
         }
-        vec_key = "".to_string();
-      } else if !key.is_empty() && val.is_empty() {
-        vec_key = key.to_string();
-        props.insert(key.to_string(), Prop::Vec(vec![]));
-      } else {
-        vec_key = "".to_string();
-      }
-    } else if let Some(pos) = line.find('-') {
-      let pre = line[..pos].trim();
-      if !pre.is_empty() {
-        continue;
-      }
-      if vec_key.is_empty() {
-        continue;
-      }
-      let val = line[(pos + 1)..].trim();
-      if val.is_empty() {
-        continue;
-      }
+        Ok(())
+      },
+    )?;
+  }
+  println!("assets: {:?}", exporter.asset_dst);
+  println!("{published} of {total} notes would be published");
 
-      if let Some(Prop::Vec(vec)) = props.get_mut(&vec_key) {
-        vec.push(val.to_string());
-      }
+  Ok(())
+}
+
+fn pipe(args: PipeArgs, config: &Config) -> Result<()> {
+  let mut src_lines = io::stdin().lines().map_while(Result::ok).peekable();
+  let src_props = extract_src_props(&mut src_lines)
+    .context("Invalid frontmatter")?
+    .unwrap_or_default();
+  let rules = PropRules::new(config)?;
+  let content_rules = ContentRules::new(config);
+  let body = public_lines(src_lines.collect(), &content_rules);
+  // There is no Hugo dir to copy the assets to
+  let mut on_asset = |name: &str, url: &str| {
+    warn!(
+      asset = name,
+      link = rules.slug.asset_url(url),
+      "asset not copied"
+    );
+    Ok(())
+  };
+  // Piped notes are in no folder, only the defaults of the whole vault apply
+  let defaults = rules
+    .cascade
+    .get(Path::new(""))
+    .cloned()
+    .unwrap_or_default();
+  let dst_props = build_dst_props(
+    &src_props,
+    &defaults,
+    &body,
+    &rules,
+    args.title.as_deref(),
+    &NoteDates {
+      modified: OffsetDateTime::now_utc(),
+      git: None,
+      name: args.title.as_deref(),
+    },
+    &mut on_asset,
+  )?;
+
+  let mut writer = io::stdout().lock();
+  match &rules.template {
+    Some(template) => {
+      let title = args.title.as_deref().unwrap_or_default();
+      let vars = TemplateVars {
+        note: &src_props,
+        props: &dst_props,
+        // Piped notes are in no vault
+        path: "",
+        slug: &rules.slug.to_url(title),
+        date: dst_props.get("date"),
+        lastmod: dst_props.get("lastmod"),
+      };
+      template.render(&mut writer, &vars)?;
     }
+    None => write_props(&mut writer, &dst_props, rules.format)?,
   }
-  if props.len() > 0 {
-    Some(props)
-  } else {
-    None
-  }
+  // There is no vault to embed notes from
+  write_content(
+    &mut writer,
+    body.into_iter(),
+    &content_rules,
+    &mut on_asset,
+    &mut |name| Ok(Some(default_link_url(name, &content_rules))),
+    &mut |_| Ok(None),
+  )?;
+  writer.flush()?;
+
+  Ok(())
 }
 
-fn str_to_vec(val: &str) -> Option<Vec<String>> {
-  if val.starts_with('[') && val.ends_with(']') {
-    let items = val[1..val.len() - 1]
-      .split(',')
-      .map(|item| item.trim().trim_matches('"').to_string())
-      .collect();
-    Some(items)
-  } else {
-    None
+fn completions(shell: Shell, args: &GlobalArgs) -> Result<()> {
+  // Profile names are baked into the script, regenerate it after adding profiles
+  let profiles = config::profile_names(args)?;
+  let mut cmd = Cli::command();
+  if !profiles.is_empty() {
+    cmd = cmd.mut_arg("profile", |arg| {
+      arg.value_parser(PossibleValuesParser::new(profiles))
+    });
   }
+  let name = cmd.get_name().to_string();
+  clap_complete::generate(shell, &mut cmd, name, &mut io::stdout());
+  Ok(())
 }
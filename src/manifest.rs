@@ -0,0 +1,86 @@
+use std::{
+  collections::BTreeMap,
+  fs,
+  path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+/// Posts exported into a Hugo dir, kept between runs to only convert the notes that changed
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Manifest {
+  /// Hash of the settings and tecexp version the posts were exported with
+  pub config: String,
+  /// Exported note of every dst post
+  pub posts: BTreeMap<PathBuf, ManifestPost>,
+}
+
+/// Note exported to a dst post
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestPost {
+  pub src: PathBuf,
+  /// Hash of the note content
+  pub hash: String,
+  /// Assets referenced by the note
+  pub assets: Vec<ManifestAsset>,
+  /// Url names of the notes linked by the note
+  #[serde(default)]
+  pub links: Vec<String>,
+  /// Notes whose content the note embeds, the post changing with them
+  #[serde(default)]
+  pub embeds: Vec<PathBuf>,
+  /// Paths of the notes the note links to or embeds
+  #[serde(default)]
+  pub linked_notes: Vec<PathBuf>,
+  /// Notes linking to the note when it was exported, listed in its post
+  #[serde(default)]
+  pub backlinks: Vec<PathBuf>,
+  /// Names of the linked and embedded notes found in no vault
+  #[serde(default)]
+  pub broken_links: Vec<String>,
+}
+
+/// Asset copied for a note
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestAsset {
+  pub src: PathBuf,
+  pub dst: PathBuf,
+}
+
+impl Manifest {
+  /// Read the manifest at `path`, an empty one if it is missing or unreadable
+  pub fn load(path: &Path) -> Self {
+    let Ok(text) = fs::read_to_string(path) else {
+      return Self::default();
+    };
+    serde_json::from_str(&text).unwrap_or_else(|error| {
+      warn!(manifest = %path.display(), %error, "invalid manifest, exporting all notes");
+      Self::default()
+    })
+  }
+
+  pub fn save(&self, path: &Path) -> Result<()> {
+    let text = serde_json::to_string_pretty(self)?;
+    fs::write(path, text).with_context(|| format!("Cannot write manifest {path:?}"))
+  }
+
+  /// Whether a post references the asset copied to `asset_dst`
+  pub fn is_asset_used(&self, asset_dst: &Path) -> bool {
+    self
+      .posts
+      .values()
+      .any(|post| post.assets.iter().any(|asset| asset.dst == asset_dst))
+  }
+}
+
+/// FNV-1a hash of `bytes`, stable across platforms and Rust versions
+pub fn hash(bytes: &[u8]) -> String {
+  let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+  for byte in bytes {
+    hash ^= u64::from(*byte);
+    hash = hash.wrapping_mul(0x0100_0000_01b3);
+  }
+  format!("{hash:016x}")
+}
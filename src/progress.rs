@@ -0,0 +1,86 @@
+use std::{
+  cell::Cell,
+  io::{self, Write},
+  sync::Mutex,
+};
+
+use indicatif::{ProgressBar, ProgressStyle};
+
+/// Bar currently drawn on stderr, log lines are printed above it
+static BAR: Mutex<Option<ProgressBar>> = Mutex::new(None);
+
+/// Progress bar of an export run over notes counted beforehand
+pub struct Progress {
+  bar: ProgressBar,
+  exported: Cell<u64>,
+  skipped: Cell<u64>,
+  copied: Cell<u64>,
+}
+
+impl Progress {
+  pub fn new(len: u64) -> Self {
+    let bar = ProgressBar::new(len).with_style(
+      ProgressStyle::with_template("{bar:40} {pos}/{len} notes {msg}")
+        .unwrap()
+        .progress_chars("=> "),
+    );
+    *BAR.lock().unwrap() = Some(bar.clone());
+    let progress = Self {
+      bar,
+      exported: Cell::new(0),
+      skipped: Cell::new(0),
+      copied: Cell::new(0),
+    };
+    progress.update();
+    progress
+  }
+
+  pub fn exported(&self) {
+    self.exported.set(self.exported.get() + 1);
+    self.bar.inc(1);
+    self.update();
+  }
+
+  pub fn skipped(&self) {
+    self.skipped.set(self.skipped.get() + 1);
+    self.bar.inc(1);
+    self.update();
+  }
+
+  pub fn copied(&self) {
+    self.copied.set(self.copied.get() + 1);
+    self.update();
+  }
+
+  fn update(&self) {
+    self.bar.set_message(format!(
+      "(exported {}, skipped {}, assets {})",
+      self.exported.get(),
+      self.skipped.get(),
+      self.copied.get()
+    ));
+  }
+}
+
+impl Drop for Progress {
+  fn drop(&mut self) {
+    self.bar.finish();
+    *BAR.lock().unwrap() = None;
+  }
+}
+
+/// Log writer to stderr, keeping the progress bar below the log lines
+pub struct LogWriter;
+
+impl Write for LogWriter {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    match BAR.lock().unwrap().as_ref() {
+      Some(bar) => bar.suspend(|| io::stderr().write(buf)),
+      None => io::stderr().write(buf),
+    }
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    io::stderr().flush()
+  }
+}
@@ -0,0 +1,322 @@
+use std::{
+  collections::BTreeMap,
+  fs::File,
+  io::{BufRead, BufReader},
+  iter::Peekable,
+  path::{Component, Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+use time::{
+  format_description::{self, well_known::Rfc3339, OwnedFormatItem},
+  macros::date,
+  Date, OffsetDateTime, UtcOffset,
+};
+use toml::value::Datetime;
+
+use crate::{
+  config::{Config, NestedTags, TaxonomyConfig},
+  date::{parse_date, parse_timezone},
+  slug::SlugRules,
+  template::FrontMatterTemplate,
+};
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum Prop {
+  Str(String),
+  Bool(bool),
+  Number(serde_yaml::Number),
+  /// A date or datetime, as written
+  Date(String),
+  Vec(Vec<String>),
+  /// Maps and lists of anything but strings, kept as written
+  Nested(Value),
+}
+
+impl Prop {
+  pub fn from_yaml(value: Value) -> Prop {
+    match value {
+      Value::Null => Prop::Vec(vec![]),
+      Value::Bool(b) => Prop::Bool(b),
+      Value::Number(n) => Prop::Number(n),
+      Value::String(s) if s.parse::<Datetime>().is_ok() => Prop::Date(s),
+      Value::String(s) => Prop::Str(s),
+      Value::Sequence(items) if items.iter().all(Value::is_string) => {
+        Prop::Vec(items.into_iter().filter_map(yaml_to_string).collect())
+      }
+      Value::Tagged(tagged) => Prop::from_yaml(tagged.value),
+      value @ (Value::Sequence(_) | Value::Mapping(_)) => Prop::Nested(value),
+    }
+  }
+
+  /// The values of the prop as strings, like for tags or aliases, none for maps
+  pub fn strings(&self) -> Vec<String> {
+    match self {
+      Prop::Str(s) | Prop::Date(s) => vec![s.clone()],
+      Prop::Bool(b) => vec![b.to_string()],
+      Prop::Number(n) => vec![n.to_string()],
+      Prop::Vec(v) => v.clone(),
+      Prop::Nested(Value::Sequence(items)) => {
+        items.iter().cloned().filter_map(yaml_to_string).collect()
+      }
+      Prop::Nested(_) => vec![],
+    }
+  }
+
+  /// The TOML value of the prop, dates being TOML datetimes unless `date_format` writes them otherwise
+  pub fn to_toml(&self) -> Result<toml::Value> {
+    Ok(match self {
+      Prop::Date(s) => match s.parse() {
+        Ok(date) => toml::Value::Datetime(date),
+        Err(_) => toml::Value::String(s.clone()),
+      },
+      prop => toml::Value::try_from(prop)?,
+    })
+  }
+}
+
+/// Frontmatter formats of Hugo
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FrontMatterFormat {
+  /// Between `---` lines
+  #[default]
+  Yaml,
+  /// Between `+++` lines
+  Toml,
+  /// A JSON object
+  Json,
+}
+
+/// How the props of a note become the props of its post
+#[derive(Debug, Clone)]
+pub struct PropRules {
+  /// Props copied to the post under another name, like `created` to `date`
+  pub rename: BTreeMap<String, String>,
+  /// Props never written into posts, whatever other rules say
+  pub exclude: Vec<String>,
+  /// Frontmatter format of the posts
+  pub format: FrontMatterFormat,
+  /// Template writing the frontmatter of posts instead of `format`
+  pub template: Option<FrontMatterTemplate>,
+  /// Sources of the `date` of posts, the first one the note has wins
+  pub date_from: Vec<String>,
+  /// Sources of the `lastmod` of posts
+  pub lastmod_from: Vec<String>,
+  /// Prop deciding whether a note is published
+  pub publish_key: String,
+  /// Values of the publish prop making the post a draft
+  pub publish_draft: Vec<String>,
+  /// Authors of the notes without an `author` prop
+  pub author: Vec<String>,
+  /// Hugo taxonomies besides `tags`, by name
+  pub taxonomies: BTreeMap<String, TaxonomyConfig>,
+  /// Write the cover image as `cover: {image: ...}`
+  pub cover_map: bool,
+  /// Set `math: true` on the posts of notes with math
+  pub math_prop: bool,
+  /// Add the `#tags` written in notes to their tags
+  pub inline_tags_prop: bool,
+  /// What nested tags like `area/topic` become
+  pub nested_tags: NestedTags,
+  /// Length of the descriptions generated for notes without one, none if not set
+  pub description_length: Option<usize>,
+  /// Format of the dates starting the names of daily notes
+  pub filename_date_format: OwnedFormatItem,
+  /// Format of the dates of posts, RFC 3339 if not set
+  pub date_format: Option<OwnedFormatItem>,
+  /// Offset of the dates of posts
+  pub timezone: UtcOffset,
+  /// Props given to all notes beneath a vault folder, by folder
+  pub cascade: BTreeMap<PathBuf, BTreeMap<String, Prop>>,
+  /// How the names of assets and tags become urls
+  pub slug: SlugRules,
+}
+
+impl PropRules {
+  pub fn new(config: &Config) -> Result<Self> {
+    let format = &config.filename_date_format;
+    let filename_date_format = format_description::parse_owned::<2>(format)
+      .with_context(|| format!("Invalid filename_date_format `{format}`"))?;
+    let format = &config.date_format;
+    let date_format = format
+      .as_ref()
+      .map(|format| {
+        format_description::parse_owned::<2>(format)
+          .with_context(|| format!("Invalid date_format `{format}`"))
+      })
+      .transpose()?;
+    // `.` and `Blog/` are the same folders as `` and `Blog`
+    let cascade = config
+      .cascade
+      .iter()
+      .map(|(folder, props)| {
+        let folder = folder
+          .components()
+          .filter(|component| *component != Component::CurDir)
+          .collect();
+        let props = props
+          .iter()
+          .map(|(key, value)| (key.clone(), Prop::from_yaml(value.clone())))
+          .collect();
+        (folder, props)
+      })
+      .collect();
+    Ok(Self {
+      rename: config.rename_props.clone(),
+      exclude: config.exclude_props.clone(),
+      format: config.front_matter_format,
+      template: config
+        .front_matter_template
+        .as_deref()
+        .map(FrontMatterTemplate::load)
+        .transpose()?,
+      date_from: config.date_from.clone(),
+      lastmod_from: config.lastmod_from.clone(),
+      publish_key: config.publish_key.clone(),
+      publish_draft: config.publish_draft.clone(),
+      author: config.author.clone(),
+      taxonomies: config.taxonomies.clone(),
+      cover_map: config.cover_map,
+      math_prop: config.math_prop,
+      inline_tags_prop: config.inline_tags_prop,
+      nested_tags: config.nested_tags,
+      description_length: config.description_length,
+      filename_date_format,
+      date_format,
+      timezone: parse_timezone(&config.timezone)?,
+      cascade,
+      slug: SlugRules::new(config),
+    })
+  }
+
+  /// Whether the note is exported as a Hugo draft, by its publish prop or `draft: true`
+  pub fn is_draft(&self, props: &BTreeMap<String, Prop>) -> bool {
+    let publish = props.get(&self.publish_key).map(Prop::strings);
+    matches!(props.get("draft"), Some(Prop::Bool(true)))
+      || publish.is_some_and(|values| values.iter().any(|v| self.publish_draft.contains(v)))
+  }
+
+  /// Format a date of a post in the timezone
+  pub fn format_date(&self, date: OffsetDateTime) -> String {
+    let date = date.to_offset(self.timezone);
+    match &self.date_format {
+      Some(format) => date.format(format),
+      None => date.format(&Rfc3339),
+    }
+    .unwrap_or_else(|_| date.to_string())
+  }
+
+  /// A date as written in a note, unless a date format is set
+  pub fn format_written_date(&self, date: &str) -> String {
+    match parse_date(date, self.timezone) {
+      Some(parsed) if self.date_format.is_some() => self.format_date(parsed),
+      _ => date.to_string(),
+    }
+  }
+
+  /// The date starting the name of a daily note, like `2024-05-12 Some title`
+  pub fn filename_date(&self, name: &str) -> Option<Date> {
+    // The formatted length of any date, as the formats of daily notes have fixed widths
+    let len = date!(2000 - 01 - 01)
+      .format(&self.filename_date_format)
+      .ok()?
+      .len();
+    Date::parse(name.get(..len)?, &self.filename_date_format).ok()
+  }
+}
+
+/// The string of a scalar YAML value
+fn yaml_to_string(value: Value) -> Option<String> {
+  match value {
+    Value::Bool(b) => Some(b.to_string()),
+    Value::Number(n) => Some(n.to_string()),
+    Value::String(s) => Some(s),
+    Value::Tagged(tagged) => yaml_to_string(tagged.value),
+    Value::Null | Value::Sequence(_) | Value::Mapping(_) => None,
+  }
+}
+
+/// Read the frontmatter props of a md file, `None` if it has no frontmatter
+pub fn read_src_props(src: &Path) -> Result<Option<BTreeMap<String, Prop>>> {
+  let src_file = File::open(src)?;
+  let mut src_lines = BufReader::new(src_file)
+    .lines()
+    .map_while(Result::ok)
+    .peekable();
+  extract_src_props(&mut src_lines).with_context(|| format!("Invalid frontmatter in {src:?}"))
+}
+
+/// Parse the frontmatter at the start of `lines`, leaving the lines after it.
+/// Notes have YAML frontmatter, posts may have TOML or JSON frontmatter too.
+/// `None` if there is no frontmatter or it has no props.
+pub fn extract_src_props(
+  lines: &mut Peekable<impl Iterator<Item = String>>,
+) -> Result<Option<BTreeMap<String, Prop>>> {
+  while let Some(line) = lines.peek() {
+    if line.is_empty() {
+      lines.next();
+    } else {
+      break;
+    }
+  }
+  let format = match lines.peek().map(|line| line.trim()) {
+    Some("---") => FrontMatterFormat::Yaml,
+    Some("+++") => FrontMatterFormat::Toml,
+    Some("{") => FrontMatterFormat::Json,
+    _ => return Ok(None),
+  };
+  let fence = lines.next().unwrap_or_default();
+
+  let mut text = String::new();
+  if format == FrontMatterFormat::Json {
+    text.push_str("{\n");
+  }
+  for line in lines.by_ref() {
+    if format == FrontMatterFormat::Json && line.trim_end() == "}" {
+      text.push_str("}\n");
+      break;
+    }
+    if line.trim() == fence.trim() {
+      break;
+    }
+    text.push_str(&line);
+    text.push('\n');
+  }
+
+  let value: Value = match format {
+    FrontMatterFormat::Yaml => serde_yaml::from_str(&text)?,
+    FrontMatterFormat::Toml => toml::from_str(&text)?,
+    FrontMatterFormat::Json => serde_json::from_str(&text)?,
+  };
+  let props: BTreeMap<String, Value> = match value {
+    Value::Null => BTreeMap::new(),
+    value => serde_yaml::from_value(value)?,
+  };
+  let props: BTreeMap<String, Prop> = props
+    .into_iter()
+    .map(|(key, value)| (key, Prop::from_yaml(value)))
+    .collect();
+  Ok((!props.is_empty()).then_some(props))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn to_toml_writes_custom_dates_as_strings() {
+    let date = Prop::Date("2024-05-12T10:30:00+02:00".to_string());
+    assert!(matches!(date.to_toml().unwrap(), toml::Value::Datetime(_)));
+    // As written with `date_format = "[month]/[day]/[year]"`
+    let date = Prop::Date("05/12/2024".to_string());
+    assert_eq!(
+      date.to_toml().unwrap(),
+      toml::Value::String("05/12/2024".to_string())
+    );
+  }
+}
@@ -0,0 +1,114 @@
+use std::collections::BTreeMap;
+
+use clap::ValueEnum;
+use serde::Deserialize;
+
+use crate::{config::Config, props::Prop};
+
+/// Decides from its props whether a note is published
+pub trait Publish {
+  fn is_published(&self, props: &BTreeMap<String, Prop>) -> bool;
+}
+
+/// Which predicates select the notes to publish
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PublishBy {
+  /// The publish prop
+  Prop,
+  /// The publish tags
+  Tag,
+  /// The publish prop or the publish tags
+  Either,
+  /// The publish prop and the publish tags
+  Both,
+}
+
+/// The publish prop of the note has one of the accepted values
+pub struct PropValue {
+  pub key: String,
+  pub values: Vec<String>,
+  /// Accepted values exporting the note as a draft
+  pub drafts: Vec<String>,
+  /// Only accept the values, not YAML booleans like `true`
+  pub strict: bool,
+}
+
+impl PropValue {
+  fn accepts(&self, value: &str) -> bool {
+    self.values.iter().chain(&self.drafts).any(|v| v.eq(value))
+      || (!self.strict && is_yaml_true(value))
+  }
+}
+
+/// YAML 1.1 true values, as used by `publish: true` of Obsidian Publish
+fn is_yaml_true(value: &str) -> bool {
+  ["true", "yes", "on", "y"]
+    .iter()
+    .any(|v| v.eq_ignore_ascii_case(value.trim()))
+}
+
+impl Publish for PropValue {
+  fn is_published(&self, props: &BTreeMap<String, Prop>) -> bool {
+    props
+      .get(&self.key)
+      .is_some_and(|prop| prop.strings().iter().any(|v| self.accepts(v)))
+  }
+}
+
+/// The note is tagged with one of the given tags
+pub struct HasTag {
+  pub tags: Vec<String>,
+}
+
+impl Publish for HasTag {
+  fn is_published(&self, props: &BTreeMap<String, Prop>) -> bool {
+    let Some(note_tags) = props.get("tags").map(Prop::strings) else {
+      return false;
+    };
+    note_tags.iter().any(|note_tag| {
+      let note_tag = note_tag.trim_start_matches('#');
+      self
+        .tags
+        .iter()
+        .any(|tag| tag.trim_start_matches('#').eq_ignore_ascii_case(note_tag))
+    })
+  }
+}
+
+/// Any of the predicates holds
+pub struct AnyOf(pub Vec<Box<dyn Publish>>);
+
+impl Publish for AnyOf {
+  fn is_published(&self, props: &BTreeMap<String, Prop>) -> bool {
+    self.0.iter().any(|publish| publish.is_published(props))
+  }
+}
+
+/// All of the predicates hold
+pub struct AllOf(pub Vec<Box<dyn Publish>>);
+
+impl Publish for AllOf {
+  fn is_published(&self, props: &BTreeMap<String, Prop>) -> bool {
+    self.0.iter().all(|publish| publish.is_published(props))
+  }
+}
+
+/// Build the publish predicate of the config
+pub fn build_publish(config: &Config) -> Box<dyn Publish> {
+  let prop = Box::new(PropValue {
+    key: config.publish_key.clone(),
+    values: config.publish.clone(),
+    drafts: config.publish_draft.clone(),
+    strict: config.publish_strict,
+  });
+  let tag = Box::new(HasTag {
+    tags: config.publish_tag.clone(),
+  });
+  match config.publish_by {
+    PublishBy::Prop => prop,
+    PublishBy::Tag => tag,
+    PublishBy::Either => Box::new(AnyOf(vec![prop, tag])),
+    PublishBy::Both => Box::new(AllOf(vec![prop, tag])),
+  }
+}
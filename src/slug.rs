@@ -0,0 +1,142 @@
+use deunicode::deunicode;
+use serde::Deserialize;
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+use crate::config::Config;
+
+/// German letters and how they are written without umlauts
+const UMLAUTS: [(char, &str); 6] = [
+  ('ä', "ae"),
+  ('ö', "oe"),
+  ('ü', "ue"),
+  ('Ä', "Ae"),
+  ('Ö', "Oe"),
+  ('Ü', "Ue"),
+];
+
+/// How the letters of other scripts than latin are written in the urls of posts and assets
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SlugUnicode {
+  /// As they are, like `/posts/日记/`, browsers encoding them
+  #[default]
+  Keep,
+  /// Percent-encoded, like `/posts/%E6%97%A5%E8%AE%B0/`
+  Encode,
+}
+
+/// Whether letters of other scripts than latin are written with latin letters in slugs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SlugTransliterate {
+  /// Kept as they are, like `日记`
+  #[default]
+  None,
+  /// Written in ASCII, Chinese in pinyin like `ri-ji`
+  Ascii,
+  /// In ASCII, German umlauts as `ae`, `oe` and `ue`, like `ueberblick` for `Überblick`
+  German,
+}
+
+/// What happens when notes like `Plan!.md` and `Plan.md` have the same slug
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SlugCollision {
+  /// Stop exporting, for the notes to be renamed
+  #[default]
+  Error,
+  /// Add `-2`, `-3`... to the slugs of the notes other than the first
+  Suffix,
+}
+
+/// How the names of notes and assets become the names of their files and urls in Hugo
+#[derive(Debug, Clone, Default)]
+pub struct SlugRules {
+  pub unicode: SlugUnicode,
+  pub transliterate: SlugTransliterate,
+  pub collision: SlugCollision,
+  /// Path of the site below its domain, starting all urls
+  pub base_path: String,
+  /// Url path of the posts of notes found in no vault
+  pub posts_url: String,
+  pub assets_url: String,
+}
+
+impl SlugRules {
+  pub fn new(config: &Config) -> Self {
+    Self {
+      unicode: config.slug_unicode,
+      transliterate: config.slug_transliterate,
+      collision: config.slug_collision,
+      base_path: config.base_path.clone(),
+      posts_url: config.posts_url.clone(),
+      assets_url: config.assets_url.clone(),
+    }
+  }
+
+  /// The slug of a name or a path, like `sub-cafe-why-not.md` for `Sub/Café: Why not?.md`.
+  /// Accents are dropped, letters and digits of all scripts kept in lower case,
+  /// apostrophes removed and other punctuation, spaces and slashes turned into single dashes.
+  pub fn to_url(&self, text: &str) -> String {
+    let text = match self.transliterate {
+      SlugTransliterate::None => text.to_string(),
+      SlugTransliterate::Ascii => deunicode(text),
+      SlugTransliterate::German => {
+        let text = UMLAUTS
+          .iter()
+          .fold(text.to_string(), |text, (umlaut, ascii)| {
+            text.replace(*umlaut, ascii)
+          });
+        deunicode(&text)
+      }
+    };
+    let text: String = text
+      .nfkd()
+      .filter(|c| !is_combining_mark(*c))
+      .nfc()
+      .collect();
+    let mut slug = String::with_capacity(text.len());
+    for c in text.chars().flat_map(char::to_lowercase) {
+      if c.is_alphanumeric() || c == '_' {
+        slug.push(c);
+      } else if c == '.' {
+        // `Note!.md` is `note.md`, not `note-.md`
+        if slug.ends_with('-') {
+          slug.pop();
+        }
+        slug.push(c);
+      } else if matches!(c, '\'' | '’') {
+        // `Don't` is `dont`
+      } else if !slug.is_empty() && !slug.ends_with(['-', '.']) {
+        slug.push('-');
+      }
+    }
+    if slug.ends_with('-') {
+      slug.pop();
+    }
+    slug
+  }
+
+  /// The url of the asset of slug `slug`, like `/assets/img.png`
+  pub fn asset_url(&self, slug: &str) -> String {
+    self.url(&format!("{}{slug}", self.assets_url))
+  }
+
+  /// The url of a path of the site like `/posts/日记/`, below the base path of the site,
+  /// encoded if the rules say so
+  pub fn url(&self, path: &str) -> String {
+    let path = format!("{}{}", self.base_path, path.trim_start_matches('/'));
+    match self.unicode {
+      SlugUnicode::Keep => path,
+      SlugUnicode::Encode => path
+        .bytes()
+        .map(|byte| match byte {
+          b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+            char::from(byte).to_string()
+          }
+          byte => format!("%{byte:02X}"),
+        })
+        .collect(),
+    }
+  }
+}
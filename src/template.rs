@@ -0,0 +1,76 @@
+use std::{collections::BTreeMap, fmt, fs, io::Write, path::Path};
+
+use anyhow::{anyhow, Context as _, Result};
+use serde::Serialize;
+use tera::{Context, Kwargs, State, Tera, Value};
+
+use crate::props::Prop;
+
+const TEMPLATE_NAME: &str = "front_matter";
+
+/// A Tera template writing the whole frontmatter of posts, fences included
+#[derive(Clone)]
+pub struct FrontMatterTemplate {
+  tera: Tera,
+  /// Text of the template, a change converts all notes again
+  source: String,
+}
+
+impl fmt::Debug for FrontMatterTemplate {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("FrontMatterTemplate")
+      .field("source", &self.source)
+      .finish()
+  }
+}
+
+/// Values the template is rendered with
+#[derive(Debug, Serialize)]
+pub struct TemplateVars<'a> {
+  /// Props of the note, as written in it
+  pub note: &'a BTreeMap<String, Prop>,
+  /// Props tecexp would write into the post
+  pub props: &'a BTreeMap<String, Prop>,
+  /// Path of the note relative to its vault dir
+  pub path: &'a str,
+  /// Url name of the post, like `my-note`
+  pub slug: &'a str,
+  pub date: Option<&'a Prop>,
+  pub lastmod: Option<&'a Prop>,
+}
+
+impl FrontMatterTemplate {
+  pub fn load(path: &Path) -> Result<Self> {
+    let source = fs::read_to_string(path)
+      .with_context(|| format!("Cannot read front_matter_template {path:?}"))?;
+    let mut tera = Tera::new();
+    // Frontmatter is no HTML, and quoting is up to the `json` filter
+    tera.autoescape_on(Vec::<&str>::new());
+    tera.register_filter("json", json);
+    tera
+      .add_raw_template(TEMPLATE_NAME, &source)
+      .map_err(|error| anyhow!("{error}"))
+      .with_context(|| format!("Invalid front_matter_template {path:?}"))?;
+    Ok(Self { tera, source })
+  }
+
+  /// Write the frontmatter of a post
+  pub fn render(&self, writer: &mut dyn Write, vars: &TemplateVars) -> Result<()> {
+    let context = Context::from_serialize(vars).map_err(|error| anyhow!("{error}"))?;
+    let text = self
+      .tera
+      .render(TEMPLATE_NAME, &context)
+      .map_err(|error| anyhow!("{error}"))
+      .with_context(|| format!("Cannot render front_matter_template for {:?}", vars.path))?;
+    write!(writer, "{text}")?;
+    if !text.ends_with('\n') {
+      writeln!(writer)?;
+    }
+    Ok(())
+  }
+}
+
+/// A value as JSON, which is valid YAML too, to write strings with quotes and lists inline
+fn json(value: &Value, _: Kwargs, _: &State) -> tera::TeraResult<String> {
+  serde_json::to_string(value).map_err(tera::Error::message)
+}
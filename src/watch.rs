@@ -0,0 +1,378 @@
+use std::{
+  collections::HashSet,
+  path::{Path, PathBuf},
+  process::{self, Command},
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    mpsc::{channel, RecvTimeoutError},
+    Arc,
+  },
+  thread,
+  time::Duration,
+};
+
+use anyhow::{Context, Result};
+use notify::{
+  event::{ModifyKind, RenameMode},
+  Event, EventKind, PollWatcher, RecommendedWatcher, RecursiveMode, Watcher,
+};
+#[cfg(unix)]
+use signal_hook::{
+  consts::{SIGHUP, SIGTERM},
+  iterator::Signals,
+};
+use tracing::{debug, error, info, warn};
+
+use crate::{
+  export::{is_hidden, Exporter, Stats, Vault, DEFAULTS_FILE},
+  print_profile, webhook,
+};
+
+/// Options of the watch command
+#[derive(Debug, Clone)]
+pub struct WatchOptions {
+  /// Quiet time after an event before the batch of events is processed
+  pub debounce: Duration,
+  /// Rewrite the posts embedding a modified asset
+  pub export_asset_notes: bool,
+  /// Scan the vaults at this interval instead of relying on OS events
+  pub poll_interval: Option<Duration>,
+  /// Shell command run after the exports of a batch changed the Hugo dir
+  pub on_export: Option<String>,
+  /// Address listening for POST requests triggering a full export
+  pub webhook: Option<String>,
+  /// Bearer token the webhook requests must have
+  pub webhook_token: Option<String>,
+}
+
+/// What the watch loop waits for
+enum Message {
+  Event(notify::Result<Event>),
+  /// Ctrl-C or SIGTERM
+  Stop,
+  /// Request of a full export, from the webhook
+  Export,
+  /// SIGHUP, reload the config and export all notes
+  Reload,
+}
+
+/// Export all notes, then export notes of the vaults whenever they are created, modified or renamed,
+/// delete the posts of deleted notes and copy modified assets again.
+/// On SIGHUP, the exporters are replaced by the ones `reload` builds from the config.
+pub fn watch(
+  mut exporters: Vec<Exporter>,
+  options: &WatchOptions,
+  reload: impl Fn() -> Result<Vec<Exporter>>,
+) -> Result<()> {
+  let (tx, rx) = channel();
+  // Finish the exports in progress before stopping, unless asked twice
+  let stop = {
+    let stop_tx = tx.clone();
+    let stopping = Arc::new(AtomicBool::new(false));
+    move || {
+      if stopping.swap(true, Ordering::SeqCst) {
+        process::exit(130);
+      }
+      let _ = stop_tx.send(Message::Stop);
+    }
+  };
+  ctrlc::set_handler(stop.clone()).context("Cannot handle Ctrl-C")?;
+  #[cfg(unix)]
+  {
+    let reload_tx = tx.clone();
+    let mut signals = Signals::new([SIGTERM, SIGHUP]).context("Cannot handle signals")?;
+    thread::spawn(move || {
+      for signal in signals.forever() {
+        if signal == SIGHUP {
+          let _ = reload_tx.send(Message::Reload);
+        } else {
+          stop();
+        }
+      }
+    });
+  }
+
+  for exporter in &exporters {
+    print_profile(exporter);
+    exporter.export_all()?;
+  }
+  run_hook(options, &exporters, 0);
+
+  if let Some(addr) = &options.webhook {
+    let export_tx = tx.clone();
+    webhook::serve(addr, options.webhook_token.clone(), move || {
+      let _ = export_tx.send(Message::Export);
+    })?;
+  }
+
+  let event_tx = tx;
+  let handler = move |res| {
+    let _ = event_tx.send(Message::Event(res));
+  };
+  // OS events are missing on network and WSL mounted vaults
+  let mut watcher: Box<dyn Watcher> = match options.poll_interval {
+    Some(interval) => Box::new(PollWatcher::new(
+      handler,
+      notify::Config::default().with_poll_interval(interval),
+    )?),
+    None => Box::new(RecommendedWatcher::new(handler, notify::Config::default())?),
+  };
+  let mut src_dirs = vec![];
+  watch_dirs(watcher.as_mut(), &mut src_dirs, &exporters)?;
+
+  // Stats of the exporters replaced by a reload
+  let mut total = Stats::default();
+  let mut stop = false;
+  while !stop {
+    let Ok(first) = rx.recv() else {
+      break;
+    };
+    // Editors write a note in several steps, wait until they are done
+    let mut batch = vec![first];
+    loop {
+      match rx.recv_timeout(options.debounce) {
+        Ok(message) => batch.push(message),
+        Err(RecvTimeoutError::Timeout) => break,
+        Err(RecvTimeoutError::Disconnected) => {
+          stop = true;
+          break;
+        }
+      }
+    }
+
+    let mut events = vec![];
+    let mut export_all = false;
+    let mut reload_config = false;
+    for message in batch {
+      match message {
+        Message::Event(Ok(event)) => events.push(event),
+        Message::Event(Err(error)) => error!(%error, "watch"),
+        Message::Stop => stop = true,
+        Message::Export => export_all = true,
+        Message::Reload => reload_config = true,
+      }
+    }
+    if stop {
+      info!("stopping");
+    } else if reload_config {
+      info!("reload");
+      // A broken config keeps the previous one running
+      match reload() {
+        Ok(reloaded) => {
+          for exporter in &exporters {
+            exporter.save_manifest()?;
+            add_stats(&mut total, exporter);
+          }
+          exporters = reloaded;
+          watch_dirs(watcher.as_mut(), &mut src_dirs, &exporters)?;
+        }
+        Err(error) => error!(error = format!("{error:#}"), "reload"),
+      }
+      export_all = true;
+    }
+    debug!(events = events.len(), "batch");
+    let changes = count_changes(&exporters);
+    handle_events(&exporters, &events, options)?;
+    if export_all && !stop {
+      for exporter in &exporters {
+        print_profile(exporter);
+        exporter.export_all()?;
+      }
+    }
+    run_hook(options, &exporters, changes);
+  }
+
+  for exporter in &exporters {
+    exporter.save_manifest()?;
+    add_stats(&mut total, exporter);
+  }
+  info!(
+    exported = total.exported,
+    copied = total.copied,
+    deleted = total.deleted,
+    "stop"
+  );
+  Ok(())
+}
+
+/// Watch the vault dirs of the exporters, and stop watching the dirs of previous exporters
+fn watch_dirs(
+  watcher: &mut dyn Watcher,
+  src_dirs: &mut Vec<PathBuf>,
+  exporters: &[Exporter],
+) -> Result<()> {
+  let mut dirs: Vec<&PathBuf> = vec![];
+  for vault in exporters.iter().flat_map(|exporter| &exporter.vaults) {
+    if !dirs.contains(&&vault.src_dir) {
+      dirs.push(&vault.src_dir);
+    }
+  }
+  for src_dir in src_dirs.iter().filter(|src_dir| !dirs.contains(src_dir)) {
+    info!(dir = %src_dir.display(), "unwatch");
+    watcher.unwatch(src_dir)?;
+  }
+  for src_dir in dirs.iter().filter(|src_dir| !src_dirs.contains(src_dir)) {
+    info!(dir = %src_dir.display(), "watch");
+    watcher.watch(src_dir, RecursiveMode::Recursive)?;
+  }
+  *src_dirs = dirs.into_iter().cloned().collect();
+  Ok(())
+}
+
+/// Add the stats of the exporter to `total`
+fn add_stats(total: &mut Stats, exporter: &Exporter) {
+  let stats = *exporter.stats.borrow();
+  total.exported += stats.exported;
+  total.copied += stats.copied;
+  total.deleted += stats.deleted;
+}
+
+/// Number of files the exporters wrote, copied and deleted so far
+fn count_changes(exporters: &[Exporter]) -> u64 {
+  exporters
+    .iter()
+    .map(|exporter| {
+      let stats = exporter.stats.borrow();
+      stats.exported + stats.copied + stats.deleted
+    })
+    .sum()
+}
+
+/// Run the `--on-export` command if the exporters changed files since `changes` were counted
+fn run_hook(options: &WatchOptions, exporters: &[Exporter], changes: u64) {
+  let Some(command) = &options.on_export else {
+    return;
+  };
+  if count_changes(exporters) == changes {
+    return;
+  }
+
+  info!(command, "run");
+  let status = if cfg!(windows) {
+    Command::new("cmd").arg("/C").arg(command).status()
+  } else {
+    Command::new("sh").arg("-c").arg(command).status()
+  };
+  // A failing hook, e.g. a broken Hugo build, is fixed by the next save
+  match status {
+    Ok(status) if status.success() => {}
+    Ok(status) => warn!(command, %status, "command failed"),
+    Err(error) => warn!(command, %error, "cannot run command"),
+  }
+}
+
+/// Handle a batch of events, each renamed, modified or deleted note once
+fn handle_events(exporters: &[Exporter], events: &[Event], options: &WatchOptions) -> Result<()> {
+  let mut renames = vec![];
+  let mut paths: Vec<&PathBuf> = vec![];
+  for event in events {
+    match (event.kind, event.paths.as_slice()) {
+      (EventKind::Modify(ModifyKind::Name(RenameMode::Both)), [from, to]) if is_note(to) => {
+        renames.push((from, to));
+      }
+      (EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_), event_paths) => {
+        paths.extend(event_paths);
+      }
+      _ => {}
+    }
+  }
+  // The halves of a rename are handled by the rename
+  paths.retain(|path| !renames.iter().any(|(from, to)| from == path || to == path));
+  let mut unique_paths: Vec<&PathBuf> = vec![];
+  for path in paths {
+    if !unique_paths.contains(&path) {
+      unique_paths.push(path);
+    }
+  }
+  if renames.is_empty() && unique_paths.is_empty() {
+    return Ok(());
+  }
+
+  for exporter in exporters {
+    exporter.reset_note_index();
+    let published = exporter.published_names();
+    for (from, to) in &renames {
+      match (note_of(exporter, from), note_of(exporter, to)) {
+        (Some((from_vault, from)), Some((to_vault, to))) => {
+          exporter.rename_note((from_vault, &from), (to_vault, &to))?
+        }
+        (Some((vault, from)), None) => exporter.remove_note(vault, &from)?,
+        (None, Some((vault, to))) => exporter.export_note(vault, &to)?,
+        (None, None) => {}
+      }
+    }
+    for full_path in &unique_paths {
+      if full_path.is_dir() {
+        // A folder moved into the vault only has an event for itself
+        exporter.export_dir(full_path)?;
+      } else if is_defaults(full_path) {
+        // The notes beneath get the new defaults, those with other props are rewritten
+        if let Some(dir) = full_path.parent() {
+          exporter.export_dir(dir)?;
+        }
+      } else if let Some((vault, sub_path)) = note_of(exporter, full_path) {
+        // A note renamed to outside of the vault only has a modify event
+        if full_path.exists() {
+          exporter.export_note(vault, &sub_path)?;
+        } else {
+          exporter.remove_note(vault, &sub_path)?;
+        }
+      } else if exporter.is_asset(full_path) {
+        if full_path.is_file() {
+          exporter.update_asset(full_path, options.export_asset_notes)?;
+        }
+      } else if !full_path.exists() {
+        exporter.remove_dir(full_path)?;
+      }
+    }
+
+    // Posts embedding the notes change with them
+    let changed_notes = renames.iter().flat_map(|(from, to)| [*from, *to]).chain(
+      unique_paths
+        .iter()
+        .copied()
+        .filter(|full_path| is_note(full_path)),
+    );
+    for full_path in changed_notes {
+      exporter.export_embedding(full_path)?;
+    }
+
+    // Links to the notes published, unpublished, renamed or moved now point elsewhere
+    let changed: HashSet<String> = exporter
+      .published_names()
+      .symmetric_difference(&published)
+      .map(|(name, _)| name.clone())
+      .collect();
+    if !changed.is_empty() {
+      exporter.export_backlinks(&changed)?;
+    }
+    exporter.update_backlinks()?;
+    exporter.save_manifest()?;
+  }
+  Ok(())
+}
+
+/// Whether `full_path` is a note, not a dir, asset, hidden file or defaults of a folder
+fn is_note(full_path: &Path) -> bool {
+  full_path
+    .file_name()
+    .and_then(|name| name.to_str())
+    .is_some_and(|name| !name.starts_with('.') && name.ends_with(".md") && name != DEFAULTS_FILE)
+}
+
+/// Whether `full_path` is the `_defaults.md` of a folder
+fn is_defaults(full_path: &Path) -> bool {
+  full_path
+    .file_name()
+    .is_some_and(|name| name == DEFAULTS_FILE)
+}
+
+/// The vault and sub path of the note at `full_path`, if the exporter exports it
+fn note_of<'a>(exporter: &'a Exporter, full_path: &Path) -> Option<(&'a Vault, PathBuf)> {
+  if !is_note(full_path) {
+    return None;
+  }
+  exporter
+    .vault_of(full_path)
+    .filter(|(_, sub_path)| !is_hidden(sub_path) && exporter.filter.accept(sub_path))
+}
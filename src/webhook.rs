@@ -0,0 +1,106 @@
+use std::{
+  io::{self, BufRead, BufReader, Read, Write},
+  net::{TcpListener, TcpStream},
+  thread,
+  time::Duration,
+};
+
+use anyhow::{Context, Result};
+use tracing::{info, warn};
+
+/// Listen on `addr` in a thread, calling `on_post` for every authorized POST request
+pub fn serve(addr: &str, token: Option<String>, on_post: impl Fn() + Send + 'static) -> Result<()> {
+  let listener = TcpListener::bind(addr).with_context(|| format!("Cannot listen on {addr}"))?;
+  info!(addr, "webhook");
+  if token.is_none() {
+    warn!(
+      addr,
+      "webhook without token, anyone reaching it can trigger exports"
+    );
+  }
+
+  thread::spawn(move || {
+    for stream in listener.incoming() {
+      let res = stream
+        .map_err(Into::into)
+        .and_then(|stream| handle(stream, token.as_deref(), &on_post));
+      if let Err(error) = res {
+        warn!(%error, "webhook");
+      }
+    }
+  });
+  Ok(())
+}
+
+fn handle(stream: TcpStream, token: Option<&str>, on_post: &impl Fn()) -> Result<()> {
+  stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+  let mut reader = BufReader::new(&stream);
+  let mut request_line = String::new();
+  reader.read_line(&mut request_line)?;
+
+  let mut authorization = None;
+  let mut content_length: u64 = 0;
+  loop {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+      break;
+    }
+    let line = line.trim_end();
+    if line.is_empty() {
+      break;
+    }
+    if let Some((name, value)) = line.split_once(':') {
+      if name.eq_ignore_ascii_case("authorization") {
+        authorization = Some(value.trim().to_string());
+      } else if name.eq_ignore_ascii_case("content-length") {
+        content_length = value.trim().parse().unwrap_or(0);
+      }
+    }
+  }
+  // The body is ignored, but read so that the client gets the response
+  io::copy(
+    &mut reader.take(content_length.min(64 * 1024)),
+    &mut io::sink(),
+  )?;
+
+  let method = request_line.split_whitespace().next().unwrap_or("");
+  let authorized = match token {
+    Some(token) => authorization.is_some_and(|value| {
+      constant_time_eq(value.as_bytes(), format!("Bearer {token}").as_bytes())
+    }),
+    None => true,
+  };
+  let status = if method != "POST" {
+    "405 Method Not Allowed"
+  } else if !authorized {
+    warn!(peer = %stream.peer_addr()?, "webhook unauthorized");
+    "401 Unauthorized"
+  } else {
+    info!(peer = %stream.peer_addr()?, "webhook");
+    on_post();
+    "202 Accepted"
+  };
+  write!(
+    &stream,
+    "HTTP/1.1 {status}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+  )?;
+  Ok(())
+}
+
+/// Whether `a` equals `b`, in a time not telling how much of them matches
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+  a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn constant_time_eq_compares_bytes() {
+    assert!(constant_time_eq(b"Bearer abc", b"Bearer abc"));
+    assert!(!constant_time_eq(b"Bearer abd", b"Bearer abc"));
+    assert!(!constant_time_eq(b"Bearer ab", b"Bearer abc"));
+    assert!(constant_time_eq(b"", b""));
+  }
+}